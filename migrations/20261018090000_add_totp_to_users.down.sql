@@ -0,0 +1,3 @@
+DROP TABLE recovery_codes;
+ALTER TABLE users DROP COLUMN totp_last_used_step;
+ALTER TABLE users DROP COLUMN totp_secret;
@@ -0,0 +1,9 @@
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT;
+
+CREATE TABLE recovery_codes (
+  user_id uuid REFERENCES users(user_id) NOT NULL,
+  code_hash TEXT NOT NULL,
+  used_at timestamptz,
+  PRIMARY KEY(user_id, code_hash)
+);
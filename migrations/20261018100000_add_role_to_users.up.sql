@@ -0,0 +1,3 @@
+-- Every existing user keeps full access
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'editor';
+UPDATE users SET role = 'owner';
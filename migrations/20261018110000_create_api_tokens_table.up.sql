@@ -0,0 +1,11 @@
+-- Tokens are only stored hashed, the plain token is shown once when it is created
+CREATE TABLE api_tokens (
+  api_token_id uuid PRIMARY KEY,
+  user_id uuid NOT NULL REFERENCES users (user_id),
+  name TEXT NOT NULL,
+  token_hash TEXT NOT NULL UNIQUE,
+  created_at timestamptz NOT NULL,
+  last_used_at timestamptz,
+  revoked_at timestamptz
+);
+CREATE INDEX api_tokens_user_id_idx ON api_tokens (user_id);
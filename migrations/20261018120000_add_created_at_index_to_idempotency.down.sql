@@ -0,0 +1 @@
+DROP INDEX idempotency_created_at_idx;
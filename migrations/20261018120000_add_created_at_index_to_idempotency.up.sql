@@ -0,0 +1,2 @@
+-- Lets the cleanup job find expired keys without scanning the whole table
+CREATE INDEX idempotency_created_at_idx ON idempotency (created_at);
@@ -0,0 +1 @@
+ALTER TABLE idempotency DROP COLUMN request_fingerprint;
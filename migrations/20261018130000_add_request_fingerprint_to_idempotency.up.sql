@@ -0,0 +1,2 @@
+-- Hash of the request a key was first used with, NULL for keys saved before it was recorded
+ALTER TABLE idempotency ADD COLUMN request_fingerprint TEXT NULL;
@@ -0,0 +1,5 @@
+-- Updated by the delivery worker on every iteration, read by the readiness probe
+CREATE TABLE worker_heartbeat(
+    worker TEXT NOT NULL PRIMARY KEY,
+    last_seen_at timestamptz NOT NULL
+);
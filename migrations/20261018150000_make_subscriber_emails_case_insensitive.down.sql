@@ -0,0 +1,4 @@
+-- Merged subscriptions are not restored
+DROP INDEX subscription_email_lower_key;
+ALTER TABLE subscription ADD CONSTRAINT subscription_email_key UNIQUE (email);
+DROP TABLE merged_subscription;
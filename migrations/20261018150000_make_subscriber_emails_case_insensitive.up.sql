@@ -0,0 +1,54 @@
+-- Emails differing only by case, or by surrounding whitespace, belonged to the same reader,
+-- who got every issue twice.
+-- One subscription is kept per address, the confirmed and oldest one first,
+-- and the merges are recorded in merged_subscription for operators to review.
+CREATE TABLE merged_subscription(
+    merged_id uuid NOT NULL PRIMARY KEY,
+    -- Not a foreign key: the kept subscriber may be deleted later on
+    kept_id uuid NOT NULL,
+    merged_email TEXT NOT NULL,
+    kept_email TEXT NOT NULL,
+    merged_status TEXT NOT NULL,
+    merged_at timestamptz NOT NULL DEFAULT now()
+);
+
+WITH ranked AS (
+    SELECT
+        id,
+        email,
+        status,
+        first_value(id) OVER same_address AS kept_id,
+        first_value(email) OVER same_address AS kept_email
+    FROM subscription
+    WINDOW same_address AS (
+        PARTITION BY lower(trim(email))
+        ORDER BY status = 'confirmed' DESC, subscribed_at, id
+    )
+)
+INSERT INTO merged_subscription(merged_id, kept_id, merged_email, kept_email, merged_status)
+SELECT id, kept_id, email, kept_email, status
+FROM ranked
+WHERE id <> kept_id;
+
+-- Confirmation links already sent for a merged subscription confirm the kept one
+UPDATE subscription_token t
+SET subscription_id = m.kept_id
+FROM merged_subscription m
+WHERE t.subscription_id = m.merged_id;
+
+DELETE FROM subscription s
+USING merged_subscription m
+WHERE s.id = m.merged_id;
+
+-- Deliveries queued for several spellings of an address are sent once
+DELETE FROM issue_delivery_queue q
+USING issue_delivery_queue other
+WHERE q.newsletter_issue_id = other.newsletter_issue_id
+    AND lower(trim(q.subscriber_email)) = lower(trim(other.subscriber_email))
+    AND q.subscriber_email > other.subscriber_email;
+UPDATE issue_delivery_queue SET subscriber_email = lower(trim(subscriber_email));
+
+UPDATE subscription SET email = lower(trim(email));
+ALTER TABLE subscription DROP CONSTRAINT subscription_email_key;
+-- The same normalisation as `SubscriberEmail::parse`
+CREATE UNIQUE INDEX subscription_email_lower_key ON subscription (lower(trim(email)));
@@ -0,0 +1,8 @@
+-- Deliveries that could not be sent, kept once they leave the queue
+CREATE TABLE issue_delivery_failure(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issue (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    failed_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
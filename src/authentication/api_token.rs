@@ -0,0 +1,161 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::AuthError;
+
+// Makes leaked tokens easy to recognise, e.g. by secret scanners
+const TOKEN_PREFIX: &str = "z2p_";
+
+pub struct ApiToken {
+    pub api_token_id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+fn generate_api_token() -> Secret<String> {
+    let mut rng = rand::thread_rng();
+    let token: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    Secret::new(format!("{}{}", TOKEN_PREFIX, token))
+}
+
+// Tokens are long random strings, so a fast hash is enough to store them safely
+// and it lets us look a token up by its hash.
+fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// Returns the plain token, which is never stored.
+#[tracing::instrument(name = "Create API token", skip(db_pool))]
+pub async fn create_api_token(
+    user_id: Uuid,
+    name: &str,
+    now: DateTime<Utc>,
+    db_pool: &PgPool,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = generate_api_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (api_token_id, user_id, name, token_hash, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        hash_api_token(token.expose_secret()),
+        now
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store API token")?;
+    Ok(token)
+}
+
+#[tracing::instrument(name = "List API tokens", skip(db_pool))]
+pub async fn list_api_tokens(
+    user_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Vec<ApiToken>, anyhow::Error> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT api_token_id, name, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(db_pool)
+    .await
+    .context("Failed to retrieve API tokens")?;
+    Ok(tokens)
+}
+
+// Returns false if the user has no such active token.
+#[tracing::instrument(name = "Revoke API token", skip(db_pool))]
+pub async fn revoke_api_token(
+    user_id: Uuid,
+    api_token_id: Uuid,
+    now: DateTime<Utc>,
+    db_pool: &PgPool,
+) -> Result<bool, anyhow::Error> {
+    let updated = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET
+            revoked_at = $3
+        WHERE
+            api_token_id = $1 AND
+            user_id = $2 AND
+            revoked_at IS NULL
+        "#,
+        api_token_id,
+        user_id,
+        now
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to revoke API token")?
+    .rows_affected();
+    Ok(updated == 1)
+}
+
+// Returns the id of the user the token belongs to.
+#[tracing::instrument(name = "Validate API token", skip(token, db_pool))]
+pub async fn validate_api_token(
+    token: Secret<String>,
+    now: DateTime<Utc>,
+    db_pool: &PgPool,
+) -> Result<Uuid, AuthError> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE api_tokens SET
+            last_used_at = $2
+        WHERE
+            token_hash = $1 AND
+            revoked_at IS NULL
+        RETURNING user_id
+        "#,
+        hash_api_token(token.expose_secret()),
+        now
+    )
+    .fetch_optional(db_pool)
+    .await
+    .context("Failed to perform a query to validate the API token")?;
+
+    row.map(|r| r.user_id)
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow!("Unknown or revoked API token")))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn tokens_are_prefixed_and_unique() {
+        let first = generate_api_token();
+        let second = generate_api_token();
+        assert!(first.expose_secret().starts_with(TOKEN_PREFIX));
+        assert_eq!(first.expose_secret().len(), TOKEN_PREFIX.len() + 40);
+        assert_ne!(first.expose_secret(), second.expose_secret());
+    }
+
+    #[test]
+    fn hashes_do_not_contain_the_token() {
+        let token = generate_api_token();
+        let hash = hash_api_token(token.expose_secret());
+        assert!(!hash.contains(token.expose_secret().as_str()));
+        assert_eq!(hash, hash_api_token(token.expose_secret()));
+    }
+}
@@ -0,0 +1,150 @@
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::http::Method;
+use actix_web::web::Bytes;
+use actix_web::FromRequest;
+use actix_web_lab::middleware::Next;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use subtle::ConstantTimeEq;
+
+use crate::error::AppError;
+use crate::session_state::TypedSession;
+
+pub const CSRF_FORM_FIELD: &str = "csrf_token";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// Synchronizer token pattern: every session gets a random token, every server-rendered
+// POST form gets it as a hidden field and every unsafe request has to send it back,
+// either as a form field or in the `X-CSRF-Token` header.
+pub async fn csrf_protection(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let token = csrf_token(&session)?;
+
+    if !is_safe(req.method()) {
+        let submitted = submitted_token(&mut req).await?;
+        let is_valid = submitted
+            .map(|submitted| bool::from(submitted.as_bytes().ct_eq(token.as_bytes())))
+            .unwrap_or(false);
+        if !is_valid {
+            return Err(AppError::Forbidden(
+                "Missing or invalid CSRF token. Reload the page and try again.".to_string(),
+            )
+            .into());
+        }
+    }
+
+    let response = next.call(req).await?;
+    // Renewing the session rotates the token, the response must carry the new one
+    let token = csrf_token(&session)?;
+    add_token_to_forms(response, &token).await
+}
+
+// The token of the session, issuing one if it has none yet
+fn csrf_token(session: &TypedSession) -> Result<String, actix_web::Error> {
+    if let Some(token) = session.get_csrf_token().map_err(AppError::unexpected)? {
+        return Ok(token);
+    }
+    let token = generate_csrf_token();
+    session
+        .insert_csrf_token(&token)
+        .map_err(AppError::unexpected)?;
+    Ok(token)
+}
+
+fn generate_csrf_token() -> String {
+    let mut rng = rand::thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+// Look for the token in the header first, then in the url-encoded form body.
+// The body is put back afterwards so that handlers can still extract their form.
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(token) = req.headers().get(CSRF_HEADER) {
+        return Ok(token.to_str().ok().map(|t| t.to_string()));
+    }
+    let is_form = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("application/x-www-form-urlencoded"))
+        .unwrap_or(false);
+    if !is_form {
+        return Ok(None);
+    }
+
+    let body = req.extract::<Bytes>().await?;
+    let token = serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == CSRF_FORM_FIELD)
+                .map(|(_, value)| value)
+        });
+
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(payload.into());
+    Ok(token)
+}
+
+// Insert the token as a hidden field right after the opening tag of every POST form.
+async fn add_token_to_forms(
+    response: ServiceResponse<impl MessageBody + 'static>,
+    token: &str,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let is_html = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with("text/html"))
+        .unwrap_or(false);
+    if !is_html {
+        return Ok(response.map_into_boxed_body());
+    }
+
+    let (request, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = to_bytes(body).await.map_err(|e| {
+        let e: Box<dyn std::error::Error> = e.into();
+        AppError::unexpected(anyhow::anyhow!("Failed to read the response body: {}", e))
+    })?;
+    let html = String::from_utf8_lossy(&body);
+    let hidden_field = format!(
+        r#"<input hidden type="text" name="{}" value="{}">"#,
+        CSRF_FORM_FIELD, token
+    );
+
+    let mut output = String::with_capacity(html.len());
+    let mut rest: &str = &html;
+    while let Some(start) = rest.find("<form") {
+        let Some(end) = rest[start..].find('>').map(|end| start + end + 1) else {
+            break;
+        };
+        let tag = &rest[start..end];
+        output.push_str(&rest[..end]);
+        if tag.to_lowercase().contains(r#"method="post""#) {
+            output.push_str(&hidden_field);
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+
+    let response = response.set_body(output).map_into_boxed_body();
+    Ok(ServiceResponse::new(request, response))
+}
@@ -1,10 +1,17 @@
+use crate::authentication::{get_role, validate_api_token, AuthError, Role};
+use crate::clock::Clock;
+use crate::error::AppError;
+use crate::session_registry::SessionRegistry;
 use crate::session_state::TypedSession;
 use crate::utils;
-use actix_web::body::MessageBody;
+use actix_web::body::{BoxBody, MessageBody};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{web, FromRequest, HttpMessage};
 use actix_web_lab::middleware::Next;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -25,6 +32,16 @@ impl Deref for UserId {
     }
 }
 
+// Id of the current session in the `SessionRegistry`
+#[derive(Copy, Clone, Debug)]
+pub struct SessionId(pub Uuid);
+
+impl std::fmt::Display for SessionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 pub async fn reject_anonymous_users(
     mut req: ServiceRequest,
     next: Next<impl MessageBody>,
@@ -34,15 +51,108 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(utils::error_500)? {
-        Some(user_id) => {
+    let user_id = session.get_user_id().map_err(AppError::unexpected)?;
+    let session_id = session.get_session_id().map_err(AppError::unexpected)?;
+    match (user_id, session_id) {
+        (Some(user_id), Some(session_id)) => {
+            let registry = req
+                .app_data::<web::Data<SessionRegistry>>()
+                .ok_or_else(|| AppError::unexpected(anyhow::anyhow!("Missing session registry")))?;
+            let clock = req
+                .app_data::<web::Data<Clock>>()
+                .ok_or_else(|| AppError::unexpected(anyhow::anyhow!("Missing clock")))?;
+            let is_active = registry
+                .touch(session_id, user_id, clock.now())
+                .await
+                .map_err(AppError::unexpected)?;
+            if !is_active {
+                session.logout();
+                return Err(login_redirect(
+                    "The session has been revoked or has expired",
+                ));
+            }
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(SessionId(session_id));
             next.call(req).await
         }
-        None => {
-            let response = utils::see_other("/login");
-            let error = anyhow::anyhow!("The user must be logged in");
-            Err(InternalError::from_response(error, response).into())
+        // Sessions without an id predate the registry and cannot be revoked, log them out
+        (Some(_), None) => {
+            session.logout();
+            Err(login_redirect("The session is not registered"))
+        }
+        (None, _) => Err(login_redirect("The user must be logged in")),
+    }
+}
+
+fn login_redirect(reason: &'static str) -> actix_web::Error {
+    let response = utils::see_other("/login");
+    InternalError::from_response(anyhow::anyhow!(reason), response).into()
+}
+
+// Must be wrapped inside `reject_anonymous_users`, which provides the `UserId`.
+pub async fn reject_non_owners(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let user_id = req.extensions().get::<UserId>().copied().ok_or_else(|| {
+        AppError::unexpected(anyhow::anyhow!("Missing user id, is the user logged in?"))
+    })?;
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| AppError::unexpected(anyhow::anyhow!("Missing database pool")))?;
+
+    match get_role(*user_id, db_pool)
+        .await
+        .map_err(AppError::unexpected)?
+    {
+        Role::Owner => next.call(req).await,
+        Role::Editor => {
+            Err(AppError::Forbidden("Only owners can access this page".to_string()).into())
+        }
+    }
+}
+
+// Authenticates API clients through an `Authorization: Bearer <token>` header.
+// Unlike browser routes there is nothing to redirect to, failures are a 401.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| Secret::new(v.trim().to_string()));
+    let Some(token) = token else {
+        return Ok(api_unauthorized(
+            req,
+            "Missing bearer token in the Authorization header".to_string(),
+        ));
+    };
+    let db_pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| AppError::unexpected(anyhow::anyhow!("Missing database pool")))?;
+    let clock = req
+        .app_data::<web::Data<Clock>>()
+        .ok_or_else(|| AppError::unexpected(anyhow::anyhow!("Missing clock")))?;
+
+    match validate_api_token(token, clock.now(), db_pool).await {
+        Ok(user_id) => {
+            req.extensions_mut().insert(UserId(user_id));
+            Ok(next.call(req).await?.map_into_boxed_body())
         }
+        Err(AuthError::InvalidCredentials(e)) => Ok(api_unauthorized(req, e.to_string())),
+        Err(AuthError::UnexpectedError(e)) => Err(AppError::unexpected(e).into()),
     }
 }
+
+// A response rather than an error, to carry the `WWW-Authenticate` challenge
+fn api_unauthorized(req: ServiceRequest, detail: String) -> ServiceResponse<BoxBody> {
+    let mut response =
+        ServiceResponse::from_err(AppError::Unauthorized(detail), req.into_parts().0);
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    response
+}
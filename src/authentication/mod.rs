@@ -1,5 +1,15 @@
+mod api_token;
+mod csrf;
 mod middleware;
 mod password;
+mod role;
+mod throttling;
+mod totp;
 
+pub use api_token::*;
+pub use csrf::*;
 pub use middleware::*;
 pub use password::*;
+pub use role::*;
+pub use throttling::*;
+pub use totp::*;
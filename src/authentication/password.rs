@@ -5,6 +5,8 @@ use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 use uuid::Uuid;
 
+use crate::authentication::Role;
+
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
     #[error("Invalid credentials")]
@@ -79,6 +81,35 @@ pub async fn change_password(
     Ok(())
 }
 
+// Users are otherwise only created by migrations (the seed `admin`), see the `create-admin` command
+#[tracing::instrument(name = "Create user", skip(password, db_pool))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+    db_pool: &PgPool,
+) -> Result<Uuid, anyhow::Error> {
+    let password_hash =
+        crate::telemetry::spawn_blocking_with_tracing(move || compute_password_hash(password))
+            .await?
+            .context("Failed to hash password")?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password, role)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+        role.as_str()
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to store the new user")?;
+    Ok(user_id)
+}
+
 #[tracing::instrument(name = "Get stored credentials", skip(username, db_pool))]
 async fn get_stored_credentials(
     username: &str,
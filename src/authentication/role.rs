@@ -0,0 +1,48 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    // Owners run the newsletter and can see and change its security settings
+    Owner,
+    Editor,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Editor => "editor",
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "owner" => Ok(Role::Owner),
+            "editor" => Ok(Role::Editor),
+            other => Err(format!("{} is not a valid role", other)),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get user role", skip(db_pool))]
+pub async fn get_role(user_id: Uuid, db_pool: &PgPool) -> Result<Role, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to perform a query to retrieve the user's role")?;
+
+    Role::try_from(row.role).map_err(|e| anyhow::anyhow!(e))
+}
@@ -0,0 +1,153 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::config::LoginThrottlingSettings;
+use crate::rate_limit::{Limit, RateLimiter};
+
+const USERNAME_KEY_PREFIX: &str = "login:username:";
+const IP_KEY_PREFIX: &str = "login:ip:";
+
+// Counts failed login attempts per username and per client IP.
+// Once either counter reaches its threshold, further attempts are refused
+// until the window expires, without paying for a password hash verification.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    limiter: RateLimiter,
+    per_username: Limit,
+    per_ip: Limit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutKind {
+    Username,
+    Ip,
+}
+
+pub struct Lockout {
+    pub kind: LockoutKind,
+    pub subject: String,
+    pub failures: u64,
+    pub retry_after: Duration,
+}
+
+impl LockoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutKind::Username => "username",
+            LockoutKind::Ip => "ip",
+        }
+    }
+
+    fn key_prefix(&self) -> &'static str {
+        match self {
+            LockoutKind::Username => USERNAME_KEY_PREFIX,
+            LockoutKind::Ip => IP_KEY_PREFIX,
+        }
+    }
+}
+
+impl TryFrom<String> for LockoutKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "username" => Ok(LockoutKind::Username),
+            "ip" => Ok(LockoutKind::Ip),
+            other => Err(format!("{} is not a valid lockout kind", other)),
+        }
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(limiter: RateLimiter, settings: &LoginThrottlingSettings) -> Self {
+        Self {
+            limiter,
+            per_username: Limit {
+                max_hits: settings.max_failures_per_username,
+                window: settings.window(),
+            },
+            per_ip: Limit {
+                max_hits: settings.max_failures_per_ip,
+                window: settings.window(),
+            },
+        }
+    }
+
+    fn limit(&self, kind: LockoutKind) -> Limit {
+        match kind {
+            LockoutKind::Username => self.per_username,
+            LockoutKind::Ip => self.per_ip,
+        }
+    }
+
+    // Counts an attempt against the username and the client IP, and returns how long
+    // the caller must wait if either is locked out. Attempts are counted before the
+    // credentials are verified, so that concurrent guesses cannot exceed the limits
+    // and locked out callers do not cost a password hash verification.
+    #[tracing::instrument(name = "Count login attempt", skip(self))]
+    pub async fn attempt(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut retry_after = self
+            .limiter
+            .hit(&username_key(username), self.per_username)
+            .await?;
+        if let Some(ip) = ip {
+            let ip_retry_after = self.limiter.hit(&ip_key(ip), self.per_ip).await?;
+            retry_after = retry_after.max(ip_retry_after);
+        }
+        Ok(retry_after)
+    }
+
+    // A successful attempt is not a failure, but it does not clear earlier failures either:
+    // only `reset`, once the user is fully authenticated, does.
+    #[tracing::instrument(name = "Forgive login attempt", skip(self))]
+    pub async fn forgive(&self, username: &str, ip: Option<IpAddr>) -> Result<(), anyhow::Error> {
+        self.limiter
+            .undo_hit(&username_key(username), self.per_username)
+            .await?;
+        if let Some(ip) = ip {
+            self.limiter.undo_hit(&ip_key(ip), self.per_ip).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn reset(&self, username: &str) -> Result<(), anyhow::Error> {
+        self.limiter.reset(&username_key(username)).await
+    }
+
+    // All usernames and IPs that are currently locked out.
+    pub async fn lockouts(&self) -> Result<Vec<Lockout>, anyhow::Error> {
+        let mut lockouts = Vec::new();
+        for kind in [LockoutKind::Username, LockoutKind::Ip] {
+            let limit = self.limit(kind);
+            for counter in self.limiter.counters(kind.key_prefix()).await? {
+                if counter.hits >= limit.max_hits {
+                    lockouts.push(Lockout {
+                        kind,
+                        subject: counter.key[kind.key_prefix().len()..].to_string(),
+                        failures: counter.hits,
+                        retry_after: counter.expires_in,
+                    });
+                }
+            }
+        }
+        Ok(lockouts)
+    }
+
+    pub async fn unlock(&self, kind: LockoutKind, subject: &str) -> Result<(), anyhow::Error> {
+        self.limiter
+            .reset(&format!("{}{}", kind.key_prefix(), subject))
+            .await
+    }
+}
+
+fn username_key(username: &str) -> String {
+    format!("{}{}", USERNAME_KEY_PREFIX, username)
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("{}{}", IP_KEY_PREFIX, ip)
+}
@@ -0,0 +1,312 @@
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+use crate::authentication::AuthError;
+
+const ISSUER: &str = "zero2prod";
+const TOTP_STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn generate_totp_secret() -> Secret<String> {
+    Secret::new(totp_rs::Secret::generate_secret().to_encoded().to_string())
+}
+
+fn build_totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow!("{:?}", e))
+        .context("Failed to decode TOTP secret")?;
+    // Skew is handled by `matching_step` so that we know which step a code belongs to
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        TOTP_STEP_SECONDS,
+        secret,
+        Some(ISSUER.to_string()),
+        username.to_string(),
+    )
+    .context("Failed to build TOTP")
+}
+
+// The otpauth:// URI that authenticator apps consume, either typed in or scanned as a QR code.
+pub fn totp_provisioning_uri(
+    secret: &Secret<String>,
+    username: &str,
+) -> Result<String, anyhow::Error> {
+    Ok(build_totp(secret, username)?.get_url())
+}
+
+// Base64-encoded PNG of the provisioning URI, ready to be embedded in an <img> tag.
+pub fn totp_qr_code_base64(
+    secret: &Secret<String>,
+    username: &str,
+) -> Result<String, anyhow::Error> {
+    build_totp(secret, username)?
+        .get_qr_base64()
+        .map_err(|e| anyhow!(e))
+        .context("Failed to render TOTP QR code")
+}
+
+// Returns the time step a code is valid for, allowing for one step of clock drift either way.
+fn matching_step(
+    secret: &Secret<String>,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<Option<i64>, anyhow::Error> {
+    let totp = build_totp(secret, "")?;
+    let now = now.timestamp() as u64;
+    for time in [
+        now.saturating_sub(TOTP_STEP_SECONDS),
+        now,
+        now + TOTP_STEP_SECONDS,
+    ] {
+        if totp.check(code, time) {
+            return Ok(Some((time / TOTP_STEP_SECONDS) as i64));
+        }
+    }
+    Ok(None)
+}
+
+pub fn verify_totp_code(
+    secret: &Secret<String>,
+    code: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, anyhow::Error> {
+    Ok(matching_step(secret, code.trim(), now)?.is_some())
+}
+
+pub fn generate_recovery_codes() -> Vec<Secret<String>> {
+    let mut rng = rand::thread_rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+                .map(|c| char::from(c).to_ascii_lowercase())
+                .take(10)
+                .collect();
+            Secret::new(format!("{}-{}", &code[..5], &code[5..]))
+        })
+        .collect()
+}
+
+// Recovery codes are long random strings, so a fast hash is enough to store them safely.
+fn hash_recovery_code(code: &str) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(code.trim().to_lowercase().as_bytes())
+    )
+}
+
+#[tracing::instrument(name = "Get TOTP secret", skip(db_pool))]
+pub async fn get_totp_secret(
+    user_id: Uuid,
+    db_pool: &PgPool,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT totp_secret
+        FROM users
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_one(db_pool)
+    .await
+    .context("Failed to perform a query to retrieve the TOTP secret")?;
+
+    Ok(row.totp_secret.map(Secret::new))
+}
+
+#[tracing::instrument(name = "Enable TOTP", skip(secret, recovery_codes, db_pool))]
+pub async fn enable_totp(
+    user_id: Uuid,
+    secret: &Secret<String>,
+    recovery_codes: &[Secret<String>],
+    db_pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users SET
+            totp_secret = $2,
+            totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+        secret.expose_secret()
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store TOTP secret")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete previous recovery codes")?;
+    for code in recovery_codes {
+        sqlx::query!(
+            r#"
+            INSERT INTO recovery_codes (user_id, code_hash)
+            VALUES ($1, $2)
+            "#,
+            user_id,
+            hash_recovery_code(code.expose_secret())
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store recovery code")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable TOTP")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Disable TOTP", skip(db_pool))]
+pub async fn disable_totp(user_id: Uuid, db_pool: &PgPool) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        r#"
+        UPDATE users SET
+            totp_secret = NULL,
+            totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to remove TOTP secret")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete recovery codes")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable TOTP")?;
+    Ok(())
+}
+
+// Accepts either a current TOTP code or an unused recovery code.
+// TOTP codes cannot be replayed: each time step is accepted at most once.
+#[tracing::instrument(name = "Validate second factor", skip(code, db_pool))]
+pub async fn validate_second_factor(
+    user_id: Uuid,
+    code: Secret<String>,
+    now: DateTime<Utc>,
+    db_pool: &PgPool,
+) -> Result<(), AuthError> {
+    let secret = get_totp_secret(user_id, db_pool)
+        .await?
+        .ok_or_else(|| anyhow!("The user has not enabled two-factor authentication"))?;
+
+    if let Some(step) = matching_step(&secret, code.expose_secret().trim(), now)? {
+        let updated = sqlx::query!(
+            r#"
+            UPDATE users SET
+                totp_last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(db_pool)
+        .await
+        .context("Failed to record the last used TOTP step")?
+        .rows_affected();
+        return if updated == 1 {
+            Ok(())
+        } else {
+            Err(AuthError::InvalidCredentials(anyhow!(
+                "The TOTP code has already been used"
+            )))
+        };
+    }
+
+    let updated = sqlx::query!(
+        r#"
+        UPDATE recovery_codes SET
+            used_at = now()
+        WHERE
+            user_id = $1 AND
+            code_hash = $2 AND
+            used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code.expose_secret())
+    )
+    .execute(db_pool)
+    .await
+    .context("Failed to redeem recovery code")?
+    .rows_affected();
+    if updated == 1 {
+        Ok(())
+    } else {
+        Err(AuthError::InvalidCredentials(anyhow!(
+            "Invalid TOTP or recovery code"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    #[test]
+    fn a_code_for_the_current_step_is_accepted() {
+        let secret = generate_totp_secret();
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let code = build_totp(&secret, "")
+            .unwrap()
+            .generate(now.timestamp() as u64);
+        assert!(verify_totp_code(&secret, &code, now).unwrap());
+    }
+
+    #[test]
+    fn a_code_from_the_previous_step_is_accepted() {
+        let secret = generate_totp_secret();
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let code = build_totp(&secret, "")
+            .unwrap()
+            .generate(now.timestamp() as u64 - TOTP_STEP_SECONDS);
+        assert!(verify_totp_code(&secret, &code, now).unwrap());
+    }
+
+    #[test]
+    fn a_stale_code_is_rejected() {
+        let secret = generate_totp_secret();
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let code = build_totp(&secret, "")
+            .unwrap()
+            .generate(now.timestamp() as u64 - 5 * TOTP_STEP_SECONDS);
+        assert!(!verify_totp_code(&secret, &code, now).unwrap());
+    }
+
+    #[test]
+    fn recovery_code_hashes_ignore_case_and_whitespace() {
+        let code = &generate_recovery_codes()[0];
+        assert_eq!(
+            hash_recovery_code(code.expose_secret()),
+            hash_recovery_code(&format!(" {} ", code.expose_secret().to_uppercase()))
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use secrecy::Secret;
+
+use crate::authentication::{create_user, Role};
+use crate::config::Settings;
+use crate::domain::SubscriberEmail;
+use crate::issue_delivery_worker::queue_stats;
+use crate::startup::{get_db_pool, migrate_database};
+
+// Without a subcommand, the API and the delivery worker run in the same process.
+#[derive(Parser)]
+#[command(name = "zero2prod", version, about = "Newsletter delivery service")]
+pub struct Cli {
+    /// YAML file whose settings override those of the environment
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Serve the API and the admin area
+    Serve,
+    /// Deliver the newsletter issues waiting in the queue
+    Worker,
+    /// Apply the pending database migrations
+    Migrate,
+    /// Create a user who can log into the admin area
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        /// Prefer the environment variable, which stays out of the shell history
+        #[arg(long, env = "ZERO2PROD_ADMIN_PASSWORD", hide_env_values = true)]
+        password: String,
+        #[arg(long, default_value = "owner", value_parser = parse_role)]
+        role: Role,
+    },
+    /// Send an email through the configured provider, to check its settings
+    SendTestEmail {
+        /// Address to send the email to
+        recipient: String,
+    },
+    /// Inspect the delivery queue
+    Queue {
+        #[command(subcommand)]
+        command: QueueCommand,
+    },
+    /// Inspect the subscribers
+    Subscribers {
+        #[command(subcommand)]
+        command: SubscribersCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum QueueCommand {
+    /// Count the deliveries waiting in the queue, by issue
+    Stats,
+}
+
+#[derive(Subcommand)]
+pub enum SubscribersCommand {
+    /// List the subscriptions merged because their emails only differed by case
+    Merged,
+}
+
+fn parse_role(role: &str) -> Result<Role, String> {
+    Role::try_from(role.to_string())
+}
+
+// The one-off commands below print their outcome on stdout
+
+pub async fn migrate(config: &Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_db_pool(&config.database);
+    migrate_database(&db_pool).await?;
+    println!("The database is up to date");
+    Ok(())
+}
+
+pub async fn create_admin(
+    config: &Settings,
+    username: &str,
+    password: Secret<String>,
+    role: Role,
+) -> Result<(), anyhow::Error> {
+    let db_pool = get_db_pool(&config.database);
+    let user_id = create_user(username, password, role, &db_pool).await?;
+    println!("Created {} {} with id {}", role.as_str(), username, user_id);
+    Ok(())
+}
+
+pub async fn send_test_email(config: &Settings, recipient: String) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(recipient)?;
+    let email_client = config.email_client.clone().client();
+    email_client
+        .send_email(
+            &recipient,
+            "Test email",
+            "<p>The email settings of zero2prod work.</p>",
+            "The email settings of zero2prod work.",
+        )
+        .await
+        .context("Failed to send the test email")?;
+    println!("Sent a test email to {}", recipient.as_ref());
+    Ok(())
+}
+
+pub async fn print_queue_stats(config: &Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_db_pool(&config.database);
+    let stats = queue_stats(&db_pool)
+        .await
+        .context("Failed to read the delivery queue")?;
+    if stats.is_empty() {
+        println!("The delivery queue is empty");
+        return Ok(());
+    }
+    println!("{:<36}  {:>8}  title", "issue_id", "pending");
+    for issue in &stats {
+        println!(
+            "{:<36}  {:>8}  {}",
+            issue.issue_id, issue.pending_deliveries, issue.title
+        );
+    }
+    let total: i64 = stats.iter().map(|issue| issue.pending_deliveries).sum();
+    println!("{} deliveries pending", total);
+    Ok(())
+}
+
+struct MergedSubscription {
+    merged_email: String,
+    merged_status: String,
+    kept_email: String,
+}
+
+// Recorded by the migration making emails case-insensitive
+pub async fn print_merged_subscriptions(config: &Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_db_pool(&config.database);
+    let merges = sqlx::query_as!(
+        MergedSubscription,
+        r#"
+        SELECT merged_email, merged_status, kept_email
+        FROM merged_subscription
+        ORDER BY kept_email, merged_email
+        "#
+    )
+    .fetch_all(&db_pool)
+    .await
+    .context("Failed to read the merged subscriptions")?;
+    if merges.is_empty() {
+        println!("No subscription has been merged");
+        return Ok(());
+    }
+    println!("{:<40}  {:<20}  merged into", "merged_email", "status");
+    for merge in &merges {
+        println!(
+            "{:<40}  {:<20}  {}",
+            merge.merged_email, merge.merged_status, merge.kept_email
+        );
+    }
+    println!("{} subscriptions merged", merges.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+
+    use super::{Cli, Command, QueueCommand, SubscribersCommand};
+    use crate::authentication::Role;
+
+    #[test]
+    fn the_cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn the_subcommand_is_optional() {
+        let cli = Cli::try_parse_from(["zero2prod"]).unwrap();
+        assert!(cli.command.is_none());
+    }
+
+    #[test]
+    fn the_config_file_can_follow_the_subcommand() {
+        let cli = Cli::try_parse_from(["zero2prod", "worker", "--config", "prod.yaml"]).unwrap();
+        assert_eq!(cli.config.unwrap().to_str(), Some("prod.yaml"));
+    }
+
+    #[test]
+    fn queue_stats_is_a_nested_subcommand() {
+        let cli = Cli::try_parse_from(["zero2prod", "queue", "stats"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Queue {
+                command: QueueCommand::Stats
+            })
+        ));
+    }
+
+    #[test]
+    fn merged_subscribers_is_a_nested_subcommand() {
+        let cli = Cli::try_parse_from(["zero2prod", "subscribers", "merged"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Subscribers {
+                command: SubscribersCommand::Merged
+            })
+        ));
+    }
+
+    #[test]
+    fn admins_are_owners_unless_told_otherwise() {
+        let parse = |args: &[&str]| match Cli::try_parse_from(args).map(|cli| cli.command) {
+            Ok(Some(Command::CreateAdmin { role, .. })) => Ok(role),
+            Ok(_) => panic!("Not a create-admin command"),
+            Err(e) => Err(e),
+        };
+        let base = [
+            "zero2prod",
+            "create-admin",
+            "--username",
+            "ops",
+            "--password",
+            "secret",
+        ];
+
+        assert_eq!(parse(&base).unwrap(), Role::Owner);
+        assert_eq!(
+            parse(&[&base[..], &["--role", "editor"]].concat()).unwrap(),
+            Role::Editor
+        );
+        assert!(parse(&[&base[..], &["--role", "intern"]].concat()).is_err());
+    }
+}
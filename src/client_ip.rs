@@ -0,0 +1,156 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::http::header::{FORWARDED, X_FORWARDED_FOR};
+use actix_web::{web, HttpRequest};
+
+// The reverse proxies allowed to report the client IP, see `ApplicationSettings`
+#[derive(Clone, Default)]
+pub struct TrustedProxies(pub Vec<IpAddr>);
+
+// The IP to rate limit and record sessions against. Behind a reverse proxy every
+// request comes from the proxy, so its forwarding headers are used instead, but only
+// when the proxy is trusted: anyone else could send them to dodge the limits.
+// Proxies append the address they received the request from, so entries are read from
+// the right: the first one that is not a trusted proxy is the client. Whatever is left
+// of it was sent by the client and cannot be trusted.
+pub fn client_ip(request: &HttpRequest) -> Option<IpAddr> {
+    let peer_ip = request.peer_addr()?.ip();
+    let Some(trusted_proxies) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer_ip);
+    };
+    if !trusted_proxies.0.contains(&peer_ip) {
+        return Some(peer_ip);
+    }
+    let mut client_ip = peer_ip;
+    for entry in forwarded_for(request).iter().rev() {
+        match parse_ip(entry) {
+            Some(ip) => client_ip = ip,
+            // Not something a trusted proxy would have written
+            None => break,
+        }
+        if !trusted_proxies.0.contains(&client_ip) {
+            break;
+        }
+    }
+    Some(client_ip)
+}
+
+// The addresses of the `Forwarded` header, or of `X-Forwarded-For` without it, in order
+fn forwarded_for(request: &HttpRequest) -> Vec<String> {
+    let headers = request.headers();
+    let forwarded: Vec<String> = headers
+        .get_all(FORWARDED)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                name.eq_ignore_ascii_case("for")
+                    .then(|| value.trim().trim_matches('"').to_string())
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.trim().to_string())
+        .collect()
+}
+
+// Forwarding headers may carry a port along with the IP, and IPv6 addresses in brackets
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .ok()
+        .or_else(|| addr.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            addr.strip_prefix('[')
+                .and_then(|addr| addr.strip_suffix(']'))
+                .and_then(|addr| addr.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use actix_web::test::TestRequest;
+    use actix_web::web;
+
+    use super::{client_ip, TrustedProxies};
+
+    const PROXY: &str = "10.0.0.1:4000";
+
+    fn request(trusted_proxies: &[&str], forwarded_for: &str) -> TestRequest {
+        let trusted_proxies: Vec<IpAddr> = trusted_proxies
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        TestRequest::default()
+            .peer_addr(PROXY.parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies(trusted_proxies)))
+            .insert_header(("X-Forwarded-For", forwarded_for))
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn forwarding_headers_are_ignored_from_untrusted_peers() {
+        let request = request(&[], "203.0.113.7").to_http_request();
+        assert_eq!(client_ip(&request), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn trusted_proxies_report_the_client_ip() {
+        let request = request(&["10.0.0.1"], "203.0.113.7").to_http_request();
+        assert_eq!(client_ip(&request), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn entries_sent_by_the_client_are_ignored() {
+        // The client sent the first entry, the proxy appended the address it saw
+        let request = request(&["10.0.0.1"], "198.51.100.1, 203.0.113.7").to_http_request();
+        assert_eq!(client_ip(&request), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn chains_of_trusted_proxies_are_walked_from_the_right() {
+        let request = request(
+            &["10.0.0.1", "10.0.0.2"],
+            "198.51.100.1, 203.0.113.7, 10.0.0.2",
+        )
+        .to_http_request();
+        assert_eq!(client_ip(&request), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn the_forwarded_header_is_read_too() {
+        let request = TestRequest::default()
+            .peer_addr(PROXY.parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies(vec!["10.0.0.1"
+                .parse()
+                .unwrap()])))
+            .insert_header((
+                "Forwarded",
+                r#"for=198.51.100.1, for="[2001:db8::7]:4711";proto=https"#,
+            ))
+            .to_http_request();
+        assert_eq!(client_ip(&request), ip("2001:db8::7"));
+    }
+
+    #[test]
+    fn the_peer_is_used_if_a_trusted_proxy_sends_no_header() {
+        let request = TestRequest::default()
+            .peer_addr(PROXY.parse().unwrap())
+            .app_data(web::Data::new(TrustedProxies(vec!["10.0.0.1"
+                .parse()
+                .unwrap()])))
+            .to_http_request();
+        assert_eq!(client_ip(&request), ip("10.0.0.1"));
+    }
+}
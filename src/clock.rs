@@ -0,0 +1,32 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+// Time source shared by anything time-sensitive (e.g. TOTP codes).
+// Tests swap in a fixed clock so they can generate the same codes as the server.
+#[derive(Clone)]
+pub enum Clock {
+    System,
+    Fixed(Arc<Mutex<DateTime<Utc>>>),
+}
+
+impl Clock {
+    pub fn fixed(now: DateTime<Utc>) -> Self {
+        Self::Fixed(Arc::new(Mutex::new(now)))
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            Clock::Fixed(now) => *now.lock().unwrap(),
+        }
+    }
+
+    // Move a fixed clock forward. The system clock cannot be moved.
+    pub fn advance(&self, by: Duration) {
+        match self {
+            Clock::System => panic!("Cannot advance the system clock"),
+            Clock::Fixed(now) => *now.lock().unwrap() += by,
+        }
+    }
+}
@@ -1,55 +1,248 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
 use std::time::Duration;
 
+use actix_web::http::header::HeaderValue;
 use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_aux::field_attributes::{
+    deserialize_bool_from_anything, deserialize_number_from_string,
+    deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 
-use crate::domain::SubscriberEmail;
+use crate::domain::{PlusTagPolicy, SubscriberEmail, SubscriberEmailError};
 use crate::email_client::EmailClient;
+use crate::utils::error_chain_fmt;
 
-#[derive(Deserialize, Clone)]
+// actix-web's cookie `Key` needs at least this many bytes to derive its keys
+const MIN_HMAC_SECRET_LENGTH: usize = 64;
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    #[serde(serialize_with = "redact")]
     pub redis_uri: Secret<String>,
+    pub login_throttling: LoginThrottlingSettings,
+    pub subscription_protection: SubscriptionProtectionSettings,
+    pub subscriber_email: SubscriberEmailSettings,
+    pub sessions: SessionSettings,
+    pub security_headers: SecurityHeadersSettings,
+    pub idempotency: IdempotencySettings,
+    pub telemetry: TelemetrySettings,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct DatabaseSettings {
     pub username: String,
+    #[serde(serialize_with = "redact")]
     pub password: Secret<String>,
     pub host: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub database_name: String,
     pub require_ssl: bool,
+    // Apply pending migrations on startup. When off, the API refuses to start
+    // until they have been applied, e.g. with the `migrate` command.
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub auto_migrate: bool,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
     pub port: u16,
     pub host: String,
     pub base_url: String,
+    #[serde(serialize_with = "redact")]
     pub hmac_secret: Secret<String>,
+    // Serve /metrics on this port, to be kept private. Metrics are not served without it.
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub metrics_port: Option<u16>,
+    // How long in-flight requests and deliveries get to finish once a shutdown is requested
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
+    // Terminate TLS in the application, for deployments without a reverse proxy
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+    // Reverse proxies whose `Forwarded` or `X-Forwarded-For` header gives the client IP.
+    // The client IP is the rightmost entry of the header that is not one of them.
+    #[serde(default, deserialize_with = "deserialize_ip_list")]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TlsSettings {
+    // PEM files holding the certificate chain, leaf first, and its private key
+    pub cert_path: String,
+    pub key_path: String,
+    // Serve plain HTTP on this port, redirecting every request to `base_url`
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub redirect_port: Option<u16>,
+    #[serde(
+        default = "default_hsts_max_age_seconds",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    pub hsts_max_age_seconds: u64,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct EmailClientSettings {
     pub base_url: String,
     pub sender_email: String,
+    #[serde(serialize_with = "redact")]
     pub auth_token: Secret<String>,
     pub timeout_milliseconds: u64,
 }
 
+#[derive(Deserialize, Serialize, Clone)]
+pub struct LoginThrottlingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_username: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_failures_per_ip: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    // Prefix for the Redis keys holding the failure counters
+    pub key_prefix: String,
+}
+
+// Public subscriptions send an email, keep bots from using them to spam arbitrary inboxes
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SubscriptionProtectionSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_ip: u64,
+    // Catches bots rotating IPs to flood a single organisation
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_per_email_domain: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub window_seconds: u64,
+    // Prefix for the Redis keys holding the counters
+    pub key_prefix: String,
+    // Require a CAPTCHA-style challenge to be solved, if set
+    #[serde(default)]
+    pub challenge: Option<ChallengeSettings>,
+}
+
+// Any verifier speaking the "siteverify" protocol shared by hCaptcha, Turnstile and reCAPTCHA
+#[derive(Deserialize, Serialize, Clone)]
+pub struct ChallengeSettings {
+    pub verify_url: String,
+    #[serde(serialize_with = "redact")]
+    pub secret: Secret<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub timeout_milliseconds: u64,
+}
+
+// Stricter checks than the syntax for the addresses people subscribe with
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SubscriberEmailSettings {
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub reject_disposable: bool,
+    // `noreply@`, `postmaster@` and the like
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub reject_role_accounts: bool,
+    pub plus_tags: PlusTagPolicy,
+    // Disposable domains to block on top of the bundled list, one per line
+    #[serde(default)]
+    pub disposable_domains_file: Option<String>,
+    // Domains known to have MX records, one per line, checked instead of querying DNS
+    #[serde(default)]
+    pub mx_records_file: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SessionSettings {
+    // Prefix for the Redis keys of the per-user session index
+    pub key_prefix: String,
+    // Sessions end this long after login, however active they are...
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    // ...or after this long without any request
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_seconds: u64,
+    // Only send the session cookie over HTTPS
+    #[serde(deserialize_with = "deserialize_bool_from_anything")]
+    pub cookie_secure: bool,
+}
+
+// Headers added to every response unless the handler set them. An empty value omits the header.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct SecurityHeadersSettings {
+    pub content_security_policy: String,
+    pub frame_options: String,
+    pub referrer_policy: String,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct IdempotencySettings {
+    // Saved responses older than this are discarded and their key can be reused
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+    // How long a request waits for another in-flight request with the same key
+    // before giving up with a 409.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub in_flight_wait_milliseconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_interval_seconds: u64,
+    // Rows deleted per statement, so the sweeper never holds many locks at once
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub cleanup_batch_size: i64,
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct TelemetrySettings {
+    pub service_name: String,
+    // Base URL of an OpenTelemetry collector accepting OTLP/HTTP, e.g. http://localhost:4318.
+    // Spans are only logged if it is not set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+}
+
 pub enum Environment {
     LOCAL,
+    TEST,
+    STAGING,
     PROD,
 }
 
+fn default_hsts_max_age_seconds() -> u64 {
+    // One year, as recommended for HSTS preload lists
+    365 * 24 * 60 * 60
+}
+
+// A YAML list, or a comma-separated string so that environment variables can set it
+fn deserialize_ip_list<'de, D>(deserializer: D) -> Result<Vec<IpAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IpList {
+        List(Vec<IpAddr>),
+        CommaSeparated(String),
+    }
+
+    match IpList::deserialize(deserializer)? {
+        IpList::List(ips) => Ok(ips),
+        IpList::CommaSeparated(ips) => ips
+            .split(',')
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .map(|ip| ip.parse().map_err(serde::de::Error::custom))
+            .collect(),
+    }
+}
+
+// Secrets never leave the process when the settings are serialized, e.g. to log them
+fn redact<S: Serializer>(_: &Secret<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("[REDACTED]")
+}
+
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
         self.without_db().database(&self.database_name)
@@ -71,10 +264,67 @@ impl DatabaseSettings {
     }
 }
 
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+impl LoginThrottlingSettings {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+}
+
+impl SubscriptionProtectionSettings {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_seconds)
+    }
+}
+
+impl ChallengeSettings {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+impl SessionSettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_seconds)
+    }
+}
+
+impl IdempotencySettings {
+    pub fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn in_flight_wait(&self) -> Duration {
+        Duration::from_millis(self.in_flight_wait_milliseconds)
+    }
+
+    pub fn cleanup_interval(&self) -> Duration {
+        Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
 impl Environment {
+    const ALL: [Environment; 4] = [
+        Environment::LOCAL,
+        Environment::TEST,
+        Environment::STAGING,
+        Environment::PROD,
+    ];
+
     pub fn as_str(&self) -> &str {
         match self {
             Environment::LOCAL => "local",
+            Environment::TEST => "test",
+            Environment::STAGING => "staging",
             Environment::PROD => "prod",
         }
     }
@@ -84,17 +334,21 @@ impl TryFrom<String> for Environment {
     type Error = String;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        match value.to_lowercase().as_str() {
-            "local" => Ok(Environment::LOCAL),
-            "prod" => Ok(Environment::PROD),
-            other => Err(format!(
-                "{} is not a supported environment. \
-                Use either '{}' or '{}'",
-                other,
-                Environment::LOCAL.as_str(),
-                Environment::PROD.as_str()
-            )),
-        }
+        let value = value.to_lowercase();
+        Environment::ALL
+            .into_iter()
+            .find(|environment| environment.as_str() == value)
+            .ok_or_else(|| {
+                let supported: Vec<String> = Environment::ALL
+                    .iter()
+                    .map(|environment| format!("'{}'", environment.as_str()))
+                    .collect();
+                format!(
+                    "{} is not a supported environment. Use one of {}",
+                    value,
+                    supported.join(", ")
+                )
+            })
     }
 }
 
@@ -105,7 +359,7 @@ impl EmailClientSettings {
         EmailClient::new(self.base_url, sender_email, self.auth_token, timeout)
     }
 
-    pub fn sender_email(&self) -> Result<SubscriberEmail, String> {
+    pub fn sender_email(&self) -> Result<SubscriberEmail, SubscriberEmailError> {
         SubscriberEmail::parse(self.sender_email.clone())
     }
 
@@ -114,28 +368,438 @@ impl EmailClientSettings {
     }
 }
 
-pub fn get_config() -> Result<Settings, config::ConfigError> {
-    let config_dir = std::env::current_dir()
-        .expect("Failed to determine current directory")
-        .join("config");
+#[derive(thiserror::Error)]
+pub enum SettingsError {
+    #[error("{0}")]
+    UnknownEnvironment(String),
+    #[error("Failed to load the configuration")]
+    Load(#[from] config::ConfigError),
+    #[error("Failed to read the file named by {variable}")]
+    SecretFile {
+        variable: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Both {0} and {0}_FILE are set, only one of them may be")]
+    ConflictingSecret(String),
+    #[error("Invalid configuration:\n{}", .0.join("\n"))]
+    Invalid(Vec<String>),
+}
+
+impl std::fmt::Debug for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
 
-    // Detect running environment, defaulting to local
+impl Settings {
+    // Reports every problem at once rather than failing on the first one
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut problems = Vec::new();
+
+        check_http_url(
+            &mut problems,
+            "application.base_url",
+            &self.application.base_url,
+        );
+        if self.application.hmac_secret.expose_secret().len() < MIN_HMAC_SECRET_LENGTH {
+            problems.push(format!(
+                "application.hmac_secret must be at least {} bytes long",
+                MIN_HMAC_SECRET_LENGTH
+            ));
+        }
+        // Port 0 picks a free port, so two of them never clash
+        if self.application.port != 0
+            && self.application.metrics_port == Some(self.application.port)
+        {
+            problems.push("application.metrics_port must differ from application.port".into());
+        }
+        if let Some(tls) = &self.application.tls {
+            for (setting, path) in [("cert_path", &tls.cert_path), ("key_path", &tls.key_path)] {
+                if !Path::new(path).is_file() {
+                    problems.push(format!(
+                        "application.tls.{} '{}' is not a file",
+                        setting, path
+                    ));
+                }
+            }
+            if let Some(redirect_port) = tls.redirect_port {
+                if redirect_port != 0
+                    && [Some(self.application.port), self.application.metrics_port]
+                        .contains(&Some(redirect_port))
+                {
+                    problems.push(
+                        "application.tls.redirect_port must differ from the other ports".into(),
+                    );
+                }
+                // Otherwise the redirect would send clients back where they came from
+                if !self.application.base_url.starts_with("https://") {
+                    problems
+                        .push("application.base_url must be an https URL to redirect to it".into());
+                }
+            }
+        }
+        if self.database.port == 0 {
+            problems.push("database.port must not be 0".into());
+        }
+
+        check_http_url(
+            &mut problems,
+            "email_client.base_url",
+            &self.email_client.base_url,
+        );
+        if let Err(e) = self.email_client.sender_email() {
+            problems.push(format!("email_client.sender_email: {}", e));
+        }
+        check_positive(
+            &mut problems,
+            "email_client.timeout_milliseconds",
+            self.email_client.timeout_milliseconds,
+        );
+
+        // The URI may embed a password, keep it out of the report
+        if redis::parse_redis_url(self.redis_uri.expose_secret()).is_none() {
+            problems.push("redis_uri is not a valid Redis URL".into());
+        }
+
+        let throttling = &self.login_throttling;
+        check_positive(
+            &mut problems,
+            "login_throttling.max_failures_per_username",
+            throttling.max_failures_per_username,
+        );
+        check_positive(
+            &mut problems,
+            "login_throttling.max_failures_per_ip",
+            throttling.max_failures_per_ip,
+        );
+        check_positive(
+            &mut problems,
+            "login_throttling.window_seconds",
+            throttling.window_seconds,
+        );
+
+        check_positive(
+            &mut problems,
+            "sessions.ttl_seconds",
+            self.sessions.ttl_seconds,
+        );
+        check_positive(
+            &mut problems,
+            "sessions.idle_timeout_seconds",
+            self.sessions.idle_timeout_seconds,
+        );
+        if self.sessions.idle_timeout_seconds > self.sessions.ttl_seconds {
+            problems
+                .push("sessions.idle_timeout_seconds must not exceed sessions.ttl_seconds".into());
+        }
+
+        let headers = &self.security_headers;
+        for (setting, value) in [
+            ("content_security_policy", &headers.content_security_policy),
+            ("frame_options", &headers.frame_options),
+            ("referrer_policy", &headers.referrer_policy),
+        ] {
+            if HeaderValue::from_str(value).is_err() {
+                problems.push(format!(
+                    "security_headers.{} is not a valid header value",
+                    setting
+                ));
+            }
+        }
+
+        let idempotency = &self.idempotency;
+        check_positive(
+            &mut problems,
+            "idempotency.ttl_seconds",
+            idempotency.ttl_seconds,
+        );
+        check_positive(
+            &mut problems,
+            "idempotency.cleanup_interval_seconds",
+            idempotency.cleanup_interval_seconds,
+        );
+        // Postgres reads a `lock_timeout` of 0 as no timeout at all
+        check_positive(
+            &mut problems,
+            "idempotency.in_flight_wait_milliseconds",
+            idempotency.in_flight_wait_milliseconds,
+        );
+        if idempotency.cleanup_batch_size <= 0 {
+            problems.push("idempotency.cleanup_batch_size must be greater than 0".into());
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            check_http_url(&mut problems, "telemetry.otlp_endpoint", endpoint);
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(problems))
+        }
+    }
+}
+
+fn check_http_url(problems: &mut Vec<String>, setting: &str, value: &str) {
+    match reqwest::Url::parse(value) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) => {}
+        _ => problems.push(format!(
+            "{} must be an http(s) URL, got '{}'",
+            setting, value
+        )),
+    }
+}
+
+fn check_positive(problems: &mut Vec<String>, setting: &str, value: u64) {
+    if value == 0 {
+        problems.push(format!("{} must be greater than 0", setting));
+    }
+}
+
+// The environment is read from APP_ENVIRONMENT, defaulting to local
+pub fn get_config(config_file: Option<&Path>) -> Result<Settings, SettingsError> {
     let environment: Environment = std::env::var("APP_ENVIRONMENT")
         .unwrap_or_else(|_| "local".to_string())
         .try_into()
-        .expect("Failed to parse app environment");
+        .map_err(SettingsError::UnknownEnvironment)?;
+    load_config(environment, config_file)
+}
+
+// Settings come, by increasing precedence, from base.yaml, the environment's own file,
+// `config_file` if any, then APP_* environment variables and the secret files they name.
+pub fn load_config(
+    environment: Environment,
+    config_file: Option<&Path>,
+) -> Result<Settings, SettingsError> {
+    let config_dir = std::env::current_dir()
+        .expect("Failed to determine current directory")
+        .join("config");
     let environment_config_filename = format!("{}.yaml", environment.as_str());
 
-    let settings = config::Config::builder()
+    let mut builder = config::Config::builder()
         .add_source(config::File::from(config_dir.join("base.yaml")))
         .add_source(config::File::from(
             config_dir.join(environment_config_filename),
-        ))
-        .add_source(
-            config::Environment::with_prefix("APP")
-                .prefix_separator("_")
-                .separator("__"),
+        ));
+    if let Some(config_file) = config_file {
+        builder = builder.add_source(config::File::from(config_file));
+    }
+    builder = builder.add_source(
+        config::Environment::with_prefix("APP")
+            .prefix_separator("_")
+            .separator("__"),
+    );
+    for (key, value) in secrets_from_files(std::env::vars())? {
+        builder = builder.set_override(key, value)?;
+    }
+    let settings = builder.build()?.try_deserialize::<Settings>()?;
+    settings.validate()?;
+    Ok(settings)
+}
+
+// The settings that can be read from a file with a `_FILE` variable. Other variables
+// ending in `_FILE` are settings of their own, e.g. `subscriber_email.mx_records_file`.
+const SECRET_KEYS: &[&str] = &[
+    "application.hmac_secret",
+    "database.password",
+    "email_client.auth_token",
+    "redis_uri",
+    "subscription_protection.challenge.secret",
+];
+
+// `APP_APPLICATION__HMAC_SECRET_FILE=/run/secrets/hmac` sets `application.hmac_secret` to
+// the content of that file, so that secrets can be mounted instead of living in the
+// environment. Returns the settings to override, by key.
+fn secrets_from_files(
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<Vec<(String, String)>, SettingsError> {
+    let vars: HashMap<String, String> = vars.into_iter().collect();
+    let mut secrets = Vec::new();
+    for (variable, path) in &vars {
+        let Some(name) = variable
+            .strip_prefix("APP_")
+            .and_then(|name| name.strip_suffix("_FILE"))
+        else {
+            continue;
+        };
+        let key = name.to_lowercase().replace("__", ".");
+        if !SECRET_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+        let direct_variable = format!("APP_{}", name);
+        if vars.contains_key(&direct_variable) {
+            return Err(SettingsError::ConflictingSecret(direct_variable));
+        }
+        let content =
+            std::fs::read_to_string(path).map_err(|source| SettingsError::SecretFile {
+                variable: variable.clone(),
+                source,
+            })?;
+        // Editors and `echo` leave a trailing newline that is not part of the secret
+        let secret = content.trim_end_matches(['\n', '\r']).to_string();
+        secrets.push((key, secret));
+    }
+    Ok(secrets)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    use super::{load_config, secrets_from_files, Environment, SettingsError};
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn the_shipped_configuration_is_valid() {
+        // Staging and prod only get their base URL from the environment
+        for environment in [Environment::LOCAL, Environment::TEST] {
+            assert!(load_config(environment, None).is_ok());
+        }
+    }
+
+    #[test]
+    fn environments_are_parsed_case_insensitively() {
+        for name in ["local", "test", "Staging", "PROD"] {
+            let environment = Environment::try_from(name.to_string()).unwrap();
+            assert_eq!(environment.as_str(), name.to_lowercase());
+        }
+        assert!(Environment::try_from("dev".to_string()).is_err());
+    }
+
+    #[test]
+    fn a_config_file_overrides_the_environment_defaults() {
+        let path = std::env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        std::fs::write(&path, "application:\n  shutdown_timeout_seconds: 5\n").unwrap();
+
+        let config = load_config(Environment::LOCAL, Some(&path)).unwrap();
+
+        assert_eq!(config.application.shutdown_timeout_seconds, 5);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn trusted_proxies_can_be_a_comma_separated_list() {
+        let path = std::env::temp_dir().join(format!("{}.yaml", Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "application:\n  trusted_proxies: \"10.0.0.1, ::1\"\n",
         )
-        .build()?;
-    settings.try_deserialize::<Settings>()
+        .unwrap();
+
+        let config = load_config(Environment::LOCAL, Some(&path)).unwrap();
+
+        assert_eq!(
+            config.application.trusted_proxies,
+            vec![
+                "10.0.0.1".parse::<std::net::IpAddr>().unwrap(),
+                "::1".parse().unwrap()
+            ]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn secrets_are_redacted_when_serialized() {
+        let config = load_config(Environment::LOCAL, None).unwrap();
+
+        let dump = serde_json::to_value(&config).unwrap();
+
+        for secret in [
+            &dump["application"]["hmac_secret"],
+            &dump["database"]["password"],
+            &dump["email_client"]["auth_token"],
+            &dump["redis_uri"],
+        ] {
+            assert_eq!(secret, "[REDACTED]");
+        }
+        assert_eq!(dump["database"]["username"], config.database.username);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = load_config(Environment::LOCAL, None).unwrap();
+        config.application.hmac_secret = Secret::new("short".into());
+        config.email_client.sender_email = "not-an-email".into();
+        config.email_client.base_url = "localhost".into();
+        config.idempotency.cleanup_batch_size = 0;
+        config.idempotency.in_flight_wait_milliseconds = 0;
+
+        let Err(SettingsError::Invalid(problems)) = config.validate() else {
+            panic!("The configuration was accepted");
+        };
+
+        assert_eq!(problems.len(), 5, "{:?}", problems);
+        for setting in [
+            "application.hmac_secret",
+            "email_client.sender_email",
+            "email_client.base_url",
+            "idempotency.cleanup_batch_size",
+            "idempotency.in_flight_wait_milliseconds",
+        ] {
+            assert!(
+                problems.iter().any(|p| p.starts_with(setting)),
+                "{} was not reported",
+                setting
+            );
+        }
+    }
+
+    #[test]
+    fn secrets_are_read_from_files() {
+        let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        std::fs::write(&path, "s3cr3t\n").unwrap();
+
+        let secrets = secrets_from_files(vars(&[
+            ("APP_DATABASE__PASSWORD_FILE", path.to_str().unwrap()),
+            ("APP_DATABASE__PORT", "5432"),
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            secrets,
+            vec![("database.password".to_string(), "s3cr3t".to_string())]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_an_error() {
+        let outcome = secrets_from_files(vars(&[(
+            "APP_APPLICATION__HMAC_SECRET_FILE",
+            "/does/not/exist",
+        )]));
+
+        assert!(matches!(outcome, Err(SettingsError::SecretFile { .. })));
+    }
+
+    #[test]
+    fn a_secret_cannot_be_set_twice() {
+        let outcome = secrets_from_files(vars(&[
+            ("APP_EMAIL_CLIENT__AUTH_TOKEN", "token"),
+            ("APP_EMAIL_CLIENT__AUTH_TOKEN_FILE", "/run/secrets/token"),
+        ]));
+
+        assert!(matches!(outcome, Err(SettingsError::ConflictingSecret(_))));
+    }
+
+    #[test]
+    fn settings_ending_in_file_are_not_secrets() {
+        let outcome = secrets_from_files(vars(&[
+            (
+                "APP_SUBSCRIBER_EMAIL__DISPOSABLE_DOMAINS_FILE",
+                "/does/not/exist",
+            ),
+            ("APP_SUBSCRIBER_EMAIL__DISPOSABLE_DOMAINS", "example.com"),
+        ]));
+
+        assert_eq!(outcome.unwrap(), vec![]);
+    }
 }
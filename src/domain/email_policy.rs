@@ -0,0 +1,287 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SubscriberEmailSettings;
+use crate::domain::{SubscriberEmail, SubscriberEmailError};
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+// Addresses that belong to a team or a machine rather than to a reader
+const ROLE_ACCOUNTS: &[&str] = &[
+    "abuse",
+    "admin",
+    "administrator",
+    "billing",
+    "contact",
+    "do-not-reply",
+    "donotreply",
+    "help",
+    "hostmaster",
+    "info",
+    "mailer-daemon",
+    "no-reply",
+    "noreply",
+    "postmaster",
+    "root",
+    "sales",
+    "security",
+    "support",
+    "webmaster",
+];
+
+// What to do with `user+tag@domain` addresses
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PlusTagPolicy {
+    #[default]
+    Allow,
+    Reject,
+    // Subscribe `user@domain` instead
+    Strip,
+}
+
+// Tells whether a domain has MX records, so that addresses nobody can receive are refused
+#[async_trait::async_trait]
+pub trait MxResolver: Send + Sync {
+    async fn has_mx_record(&self, domain: &str) -> Result<bool, anyhow::Error>;
+}
+
+// Answers from a list of the domains known to have MX records, without any DNS query
+pub struct StaticMxResolver {
+    domains: HashSet<String>,
+}
+
+impl StaticMxResolver {
+    pub fn new(domains: &str) -> Self {
+        Self {
+            domains: domain_list(domains),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl MxResolver for StaticMxResolver {
+    async fn has_mx_record(&self, domain: &str) -> Result<bool, anyhow::Error> {
+        Ok(self.domains.contains(domain))
+    }
+}
+
+// The rules applied on top of the syntax check of `SubscriberEmail::parse`
+// to the addresses people subscribe with.
+#[derive(Clone)]
+pub struct EmailPolicy {
+    disposable_domains: Option<HashSet<String>>,
+    reject_role_accounts: bool,
+    plus_tags: PlusTagPolicy,
+    mx_resolver: Option<Arc<dyn MxResolver>>,
+}
+
+impl EmailPolicy {
+    // Only checks the syntax
+    pub fn permissive() -> Self {
+        Self {
+            disposable_domains: None,
+            reject_role_accounts: false,
+            plus_tags: PlusTagPolicy::Allow,
+            mx_resolver: None,
+        }
+    }
+
+    pub fn from_settings(settings: &SubscriberEmailSettings) -> Result<Self, anyhow::Error> {
+        let disposable_domains = if settings.reject_disposable {
+            let mut domains = domain_list(BUNDLED_DISPOSABLE_DOMAINS);
+            if let Some(path) = &settings.disposable_domains_file {
+                domains.extend(domain_list(&read_list(path)?));
+            }
+            Some(domains)
+        } else {
+            None
+        };
+        let mx_resolver = match &settings.mx_records_file {
+            Some(path) => Some(Arc::new(StaticMxResolver::new(&read_list(path)?)) as _),
+            None => None,
+        };
+        Ok(Self {
+            disposable_domains,
+            reject_role_accounts: settings.reject_role_accounts,
+            plus_tags: settings.plus_tags,
+            mx_resolver,
+        })
+    }
+
+    pub fn with_mx_resolver(mut self, mx_resolver: Arc<dyn MxResolver>) -> Self {
+        self.mx_resolver = Some(mx_resolver);
+        self
+    }
+
+    // Returns the address to subscribe, which differs from the given one if its tag is stripped
+    #[tracing::instrument(name = "Apply the subscriber email policy", skip(self))]
+    pub async fn check(
+        &self,
+        email: SubscriberEmail,
+    ) -> Result<SubscriberEmail, SubscriberEmailError> {
+        let email = self.check_plus_tag(email)?;
+        if self.is_disposable(email.domain()) {
+            return Err(SubscriberEmailError::Disposable(email.as_ref().to_string()));
+        }
+        if self.reject_role_accounts && is_role_account(email.local_part()) {
+            return Err(SubscriberEmailError::RoleAccount(
+                email.as_ref().to_string(),
+            ));
+        }
+        if let Some(mx_resolver) = &self.mx_resolver {
+            match mx_resolver.has_mx_record(email.domain()).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Err(SubscriberEmailError::NoMxRecord(email.as_ref().to_string()))
+                }
+                // Better to send a confirmation email that bounces than to turn readers away
+                Err(e) => tracing::warn!(error.cause_chain = ?e, "Failed to look up MX records"),
+            }
+        }
+        Ok(email)
+    }
+
+    fn check_plus_tag(
+        &self,
+        email: SubscriberEmail,
+    ) -> Result<SubscriberEmail, SubscriberEmailError> {
+        let Some((untagged, _)) = email.local_part().split_once('+') else {
+            return Ok(email);
+        };
+        match self.plus_tags {
+            PlusTagPolicy::Allow => Ok(email),
+            PlusTagPolicy::Reject => Err(SubscriberEmailError::PlusTag(email.as_ref().to_string())),
+            PlusTagPolicy::Strip => {
+                SubscriberEmail::parse(format!("{}@{}", untagged, email.domain()))
+            }
+        }
+    }
+
+    // Subdomains of a disposable domain are disposable too
+    fn is_disposable(&self, domain: &str) -> bool {
+        let Some(disposable_domains) = &self.disposable_domains else {
+            return false;
+        };
+        let mut candidate = domain;
+        loop {
+            if disposable_domains.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+fn is_role_account(local_part: &str) -> bool {
+    let untagged = local_part.split('+').next().unwrap_or(local_part);
+    ROLE_ACCOUNTS.contains(&untagged)
+}
+
+// One domain per line, blank lines and `#` comments are ignored
+fn domain_list(contents: &str) -> HashSet<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn read_list(path: &str) -> Result<String, anyhow::Error> {
+    std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{EmailPolicy, PlusTagPolicy, StaticMxResolver};
+    use crate::domain::{SubscriberEmail, SubscriberEmailError};
+
+    fn strict_policy() -> EmailPolicy {
+        EmailPolicy::from_settings(&crate::config::SubscriberEmailSettings {
+            reject_disposable: true,
+            reject_role_accounts: true,
+            plus_tags: PlusTagPolicy::Reject,
+            disposable_domains_file: None,
+            mx_records_file: None,
+        })
+        .unwrap()
+    }
+
+    async fn check(policy: &EmailPolicy, email: &str) -> Result<String, SubscriberEmailError> {
+        let email = SubscriberEmail::parse(email.to_string()).unwrap();
+        policy
+            .check(email)
+            .await
+            .map(|email| email.as_ref().to_string())
+    }
+
+    #[tokio::test]
+    async fn personal_addresses_are_accepted() {
+        let email = check(&strict_policy(), "ursula@example.com").await;
+        assert_eq!(email.unwrap(), "ursula@example.com");
+    }
+
+    #[tokio::test]
+    async fn disposable_domains_and_their_subdomains_are_rejected() {
+        for email in ["ursula@mailinator.com", "ursula@Inbox.YOPMAIL.com"] {
+            assert!(matches!(
+                check(&strict_policy(), email).await,
+                Err(SubscriberEmailError::Disposable(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn role_accounts_are_rejected() {
+        assert_eq!(
+            check(&strict_policy(), "NoReply@example.com").await,
+            Err(SubscriberEmailError::RoleAccount(
+                "noreply@example.com".to_string()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn plus_tags_follow_the_policy() {
+        let mut policy = strict_policy();
+        let email = "ursula+news@example.com";
+
+        assert!(matches!(
+            check(&policy, email).await,
+            Err(SubscriberEmailError::PlusTag(_))
+        ));
+        policy.plus_tags = PlusTagPolicy::Strip;
+        assert_eq!(check(&policy, email).await.unwrap(), "ursula@example.com");
+        policy.plus_tags = PlusTagPolicy::Allow;
+        assert_eq!(check(&policy, email).await.unwrap(), email);
+    }
+
+    #[tokio::test]
+    async fn the_permissive_policy_only_checks_the_syntax() {
+        let email = check(&EmailPolicy::permissive(), "noreply+x@mailinator.com").await;
+        assert_eq!(email.unwrap(), "noreply+x@mailinator.com");
+    }
+
+    #[tokio::test]
+    async fn domains_without_mx_records_are_rejected() {
+        let policy = EmailPolicy::permissive()
+            .with_mx_resolver(Arc::new(StaticMxResolver::new("example.com\n")));
+
+        assert!(check(&policy, "ursula@example.com").await.is_ok());
+        assert_eq!(
+            check(&policy, "ursula@example.invalid").await,
+            Err(SubscriberEmailError::NoMxRecord(
+                "ursula@example.invalid".to_string()
+            ))
+        );
+    }
+}
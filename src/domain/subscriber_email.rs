@@ -1,5 +1,20 @@
-#[derive(Debug)]
-pub struct SubscriberEmail(pub String);
+#[derive(Debug, Clone)]
+pub struct SubscriberEmail(String);
+
+// One variant per rule, so that callers can tell them apart
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SubscriberEmailError {
+    #[error("{0} is not a valid subscriber email.")]
+    Invalid(String),
+    #[error("{0} is a disposable email address.")]
+    Disposable(String),
+    #[error("{0} is a role address, subscribe with a personal one.")]
+    RoleAccount(String),
+    #[error("{0} has a + tag, which is not accepted.")]
+    PlusTag(String),
+    #[error("{0} cannot receive email, its domain has no MX record.")]
+    NoMxRecord(String),
+}
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
@@ -8,12 +23,26 @@ impl AsRef<str> for SubscriberEmail {
 }
 
 impl SubscriberEmail {
-    pub fn parse(email: String) -> Result<Self, String> {
-        if !validator::ValidateEmail::validate_email(&email) {
-            Err(format!("{} is not a valid subscriber email.", email))
-        } else {
-            Ok(Self(email))
+    // Surrounding whitespace is trimmed and the address lowercased: local parts may be
+    // case-sensitive in theory, but `Alice@` and `alice@` are the same reader in practice.
+    pub fn parse(email: String) -> Result<Self, SubscriberEmailError> {
+        let trimmed = email.trim();
+        if !validator::ValidateEmail::validate_email(&trimmed) {
+            return Err(SubscriberEmailError::Invalid(email));
         }
+        Ok(Self(trimmed.to_lowercase()))
+    }
+
+    pub fn local_part(&self) -> &str {
+        self.split().0
+    }
+
+    pub fn domain(&self) -> &str {
+        self.split().1
+    }
+
+    fn split(&self) -> (&str, &str) {
+        self.0.rsplit_once('@').expect("Validated emails have an @")
     }
 }
 
@@ -25,7 +54,7 @@ mod tests {
     use quickcheck::{Arbitrary, Gen};
     use quickcheck_macros::quickcheck;
 
-    use super::SubscriberEmail;
+    use super::{SubscriberEmail, SubscriberEmailError};
 
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(String);
@@ -61,6 +90,23 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn emails_are_trimmed_and_lowercased() {
+        let email = SubscriberEmail::parse(" Ursula@Le.Guin.COM\n".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@le.guin.com");
+        assert_eq!(email.local_part(), "ursula");
+        assert_eq!(email.domain(), "le.guin.com");
+    }
+
+    #[test]
+    fn invalid_emails_are_reported_as_such() {
+        let email = "ursuladomain.com".to_string();
+        assert_eq!(
+            SubscriberEmail::parse(email.clone()).unwrap_err(),
+            SubscriberEmailError::Invalid(email)
+        );
+    }
+
     #[quickcheck]
     fn valid_emails_are_parsed_successfully(email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(email.0).is_ok()
@@ -1,10 +1,13 @@
 use std::time::Duration;
 
+use metrics::counter;
 use secrecy::{ExposeSecret, Secret};
 use serde::Serialize;
 
 use crate::domain::SubscriberEmail;
+use crate::telemetry::trace_context_headers;
 
+#[derive(Clone)]
 pub struct EmailClient {
     http_client: reqwest::Client,
     base_url: String,
@@ -57,13 +60,20 @@ impl EmailClient {
             text_body: text_content,
         };
 
-        self.http_client
+        let response = self
+            .http_client
             .post(url)
             .header("X-Postmark-Server-Token", self.auth_token.expose_secret())
+            .headers(trace_context_headers())
             .json(&request_body) // also sets Content-Type header to application/json
             .send()
-            .await?
-            .error_for_status()?;
+            .await;
+        let status = match &response {
+            Ok(response) => response.status().as_u16().to_string(),
+            Err(_) => "error".to_string(),
+        };
+        counter!("email_client_responses_total", "status" => status).increment(1);
+        response?.error_for_status()?;
         Ok(())
     }
 }
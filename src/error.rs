@@ -0,0 +1,280 @@
+use std::fmt::{Debug, Formatter};
+use std::time::Duration;
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ResponseHead, ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue, ACCEPT, CONTENT_TYPE, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use actix_web_lab::middleware::Next;
+use serde::Serialize;
+use tracing_actix_web::RequestId;
+use utoipa::ToSchema;
+
+use crate::utils;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+// The error type of handlers and middlewares. The message of each variant is shown to the
+// client, except for unexpected errors whose cause chain only goes to the logs.
+#[derive(thiserror::Error)]
+pub enum AppError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{detail}")]
+    Conflict {
+        detail: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    UnprocessableEntity(String),
+    #[error("{detail}")]
+    TooManyRequests {
+        detail: String,
+        retry_after: Duration,
+    },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        utils::error_chain_fmt(self, f)
+    }
+}
+
+impl AppError {
+    pub fn unexpected(e: impl Into<anyhow::Error>) -> Self {
+        AppError::UnexpectedError(e.into())
+    }
+
+    fn detail(&self) -> String {
+        match self {
+            AppError::UnexpectedError(_) => "Something went wrong".to_string(),
+            e => e.to_string(),
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AppError::Conflict { retry_after, .. } => *retry_after,
+            AppError::TooManyRequests { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    fn problem_details(&self, request_id: Option<String>) -> ProblemDetails {
+        problem_details(self.status_code(), self.detail(), request_id)
+    }
+}
+
+fn problem_details(
+    status: StatusCode,
+    detail: String,
+    request_id: Option<String>,
+) -> ProblemDetails {
+    ProblemDetails {
+        problem_type: "about:blank",
+        title: status.canonical_reason().unwrap_or_default().to_string(),
+        status: status.as_u16(),
+        detail,
+        request_id,
+    }
+}
+
+fn html_page(status: StatusCode, detail: &str, request_id: Option<String>) -> String {
+    let detail = utils::escape_html(detail);
+    let request_id = request_id
+        .map(|id| format!("<p>Request id: <code>{}</code></p>", id))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta http-equiv="content-type" content="text/html; charset=utf-8">
+        <title>{status}</title>
+    </head>
+    <body>
+        <h1>{status}</h1>
+        <p><i>{detail}</i></p>
+        {request_id}
+        <p><a href="/">Back to the home page</a></p>
+    </body>
+</html>"#
+    )
+}
+
+// What clients are told about errors that are not `AppError`s: those raised by actix-web,
+// its extractors and actix-session. Only client errors describe themselves.
+fn generic_detail(status: StatusCode, error: Option<&actix_web::Error>) -> String {
+    match error {
+        Some(e) if status.is_client_error() => e.to_string(),
+        _ if status.is_server_error() => "Something went wrong".to_string(),
+        _ => status.canonical_reason().unwrap_or_default().to_string(),
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Conflict { .. } => StatusCode::CONFLICT,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // `render_errors` picks the final format, this is what clients get without it
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let Some(retry_after) = self.retry_after() {
+            response.insert_header((RETRY_AFTER, retry_after.as_secs().to_string()));
+        }
+        response
+            .content_type(PROBLEM_JSON)
+            .body(serde_json::to_string(&self.problem_details(None)).unwrap_or_default())
+    }
+}
+
+// RFC 7807 problem details, extended with the id of the request in our logs
+#[derive(Serialize, ToSchema)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    problem_type: &'static str,
+    #[schema(example = "Not Found")]
+    title: String,
+    #[schema(example = 404)]
+    status: u16,
+    detail: String,
+    request_id: Option<String>,
+}
+
+// Renders every error response for whoever made the request: an HTML page for browsers,
+// problem+json for everybody else. `AppError`s give their own detail, other errors
+// (actix-web's default 404, extractor and session failures) one based on their status.
+// Error responses built by handlers themselves, i.e. with a body and no error attached,
+// are left alone. Both carry the request id that `TracingLogger`
+// attaches to the log lines of the request, so it must wrap this middleware.
+pub async fn render_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let wants_html = accepts_html(&req);
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.to_string());
+    let render = |status: StatusCode, detail: String| {
+        if wants_html {
+            (
+                "text/html; charset=utf-8",
+                html_page(status, &detail, request_id.clone()),
+            )
+        } else {
+            (
+                PROBLEM_JSON,
+                serde_json::to_string(&problem_details(status, detail, request_id.clone()))
+                    .unwrap_or_default(),
+            )
+        }
+    };
+    let set_body = |head: &mut ResponseHead, (content_type, body): (&'static str, String)| {
+        let headers = head.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+        if let Some(value) = request_id
+            .as_deref()
+            .and_then(|id| HeaderValue::from_str(id).ok())
+        {
+            headers.insert(HeaderName::from_static("x-request-id"), value);
+        }
+        body.boxed()
+    };
+
+    match next.call(req).await {
+        Ok(response) => {
+            let status = response.status();
+            let detail = match response.response().error() {
+                Some(e) => match e.as_error::<AppError>() {
+                    Some(app_error) => Some(app_error.detail()),
+                    None => is_error(status).then(|| generic_detail(status, Some(e))),
+                },
+                None => (is_error(status)
+                    && response.response().body().size() == BodySize::Sized(0))
+                .then(|| generic_detail(status, None)),
+            };
+            Ok(match detail {
+                // Headers set along with the error, e.g. `WWW-Authenticate`, are kept
+                Some(detail) => {
+                    let rendered = render(status, detail);
+                    response.map_body(|head, _| set_body(head, rendered))
+                }
+                None => response.map_into_boxed_body(),
+            })
+        }
+        // Errors raised by middlewares never became a response, turn them into one
+        Err(e) => {
+            let response = e.error_response();
+            let status = response.status();
+            let detail = match e.as_error::<AppError>() {
+                Some(app_error) => app_error.detail(),
+                None => generic_detail(status, Some(&e)),
+            };
+            let rendered = render(status, detail);
+            let response = response.map_body(|head, _| set_body(head, rendered));
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+fn is_error(status: StatusCode) -> bool {
+    status.is_client_error() || status.is_server_error()
+}
+
+fn accepts_html(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("text/html"))
+        .unwrap_or(false)
+}
+
+// Turn extractor failures (malformed bodies, bad path or query parameters) into `AppError`s
+pub fn json_error_handler(
+    err: actix_web::error::JsonPayloadError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    AppError::BadRequest(err.to_string()).into()
+}
+
+pub fn form_error_handler(
+    err: actix_web::error::UrlencodedError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    AppError::BadRequest(err.to_string()).into()
+}
+
+pub fn path_error_handler(
+    err: actix_web::error::PathError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    AppError::NotFound(err.to_string()).into()
+}
+
+pub fn query_error_handler(
+    err: actix_web::error::QueryPayloadError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    AppError::BadRequest(err.to_string()).into()
+}
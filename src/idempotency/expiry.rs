@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Settings;
+
+// Periodically deletes idempotency keys that have outlived their TTL.
+pub async fn run_cleanup_until_stopped(
+    config: Settings,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let db_pool = crate::startup::get_db_pool(&config.database);
+    let settings = config.idempotency;
+    while !shutdown.is_cancelled() {
+        // Failures are logged by `delete_expired_keys`, the next run will retry
+        let _ = delete_expired_keys(&db_pool, settings.ttl(), settings.cleanup_batch_size).await;
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.cancelled() => {}
+        }
+    }
+    Ok(())
+}
+
+// Returns how many keys were deleted.
+#[tracing::instrument(skip(db_pool), fields(deleted_rows = tracing::field::Empty), err)]
+pub async fn delete_expired_keys(
+    db_pool: &PgPool,
+    ttl: Duration,
+    batch_size: i64,
+) -> Result<u64, anyhow::Error> {
+    let mut deleted_rows = 0;
+    loop {
+        // Rows locked by an in-flight request are left for the next batch or run
+        let deleted = sqlx::query!(
+            r#"
+            DELETE FROM idempotency
+            WHERE (user_id, idempotency_key) IN (
+                SELECT user_id, idempotency_key
+                FROM idempotency
+                WHERE created_at < now() - make_interval(secs => $1)
+                LIMIT $2
+                FOR UPDATE
+                SKIP LOCKED
+            )
+            "#,
+            ttl.as_secs_f64(),
+            batch_size
+        )
+        .execute(db_pool)
+        .await?
+        .rows_affected();
+        deleted_rows += deleted;
+        if deleted < batch_size as u64 {
+            break;
+        }
+    }
+    tracing::Span::current().record("deleted_rows", deleted_rows);
+    tracing::info!(deleted_rows, "Deleted expired idempotency keys");
+    Ok(deleted_rows)
+}
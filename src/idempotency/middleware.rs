@@ -0,0 +1,296 @@
+use std::fmt::{Debug, Formatter};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::time::Duration;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::web::{self, Bytes, Data};
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use actix_web_lab::middleware::Next;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+use crate::authentication::{UserId, CSRF_FORM_FIELD};
+use crate::config::IdempotencySettings;
+use crate::error::AppError;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::startup::{FORM_BODY_LIMIT, JSON_BODY_LIMIT};
+use crate::utils;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const IDEMPOTENCY_KEY_FORM_FIELD: &str = "idempotency_key";
+
+#[derive(thiserror::Error)]
+pub enum IdempotencyError {
+    #[error("Missing idempotency key, send it in the {IDEMPOTENCY_KEY_HEADER} header or the {IDEMPOTENCY_KEY_FORM_FIELD} form field")]
+    MissingKey,
+    #[error("{0}")]
+    InvalidKey(String),
+    #[error("The idempotency key has already been used for a different request")]
+    KeyReused,
+    #[error("A request with the same idempotency key is still being processed")]
+    InProgress { retry_after: Duration },
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for IdempotencyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        utils::error_chain_fmt(self, f)
+    }
+}
+
+impl From<IdempotencyError> for AppError {
+    fn from(e: IdempotencyError) -> Self {
+        match e {
+            IdempotencyError::MissingKey | IdempotencyError::InvalidKey(_) => {
+                AppError::BadRequest(e.to_string())
+            }
+            IdempotencyError::KeyReused => AppError::UnprocessableEntity(e.to_string()),
+            IdempotencyError::InProgress { retry_after } => AppError::Conflict {
+                detail: e.to_string(),
+                retry_after: Some(retry_after),
+            },
+            IdempotencyError::UnexpectedError(e) => AppError::UnexpectedError(e),
+        }
+    }
+}
+
+// The transaction holding the idempotency key of the current request.
+// Handlers behind `idempotent` make their changes through it, so that they are
+// committed together with the saved response (or not at all).
+#[derive(Clone)]
+pub struct IdempotentTransaction(Rc<Mutex<Option<Transaction<'static, Postgres>>>>);
+
+impl IdempotentTransaction {
+    pub async fn lock(&self) -> MappedMutexGuard<'_, Transaction<'static, Postgres>> {
+        MutexGuard::map(self.0.lock().await, |transaction| {
+            transaction
+                .as_mut()
+                .expect("The transaction is only taken back once the handler has returned")
+        })
+    }
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<IdempotentTransaction>()
+                .cloned()
+                .ok_or_else(|| {
+                    AppError::unexpected(anyhow::anyhow!(
+                        "The route is not wrapped by the idempotency middleware"
+                    ))
+                    .into()
+                }),
+        )
+    }
+}
+
+// Makes a mutating route idempotent: a request that reuses the key of an earlier one
+// gets the earlier response back instead of being processed again. Keys are scoped by
+// user and must be sent with the same payload every time.
+// `on_replay` runs whenever a saved response is returned, e.g. to send flash messages again.
+// Must run after the user has been authenticated.
+pub async fn idempotent(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+    on_replay: Option<fn()>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let user_id =
+        req.extensions().get::<UserId>().copied().ok_or_else(|| {
+            AppError::unexpected(anyhow::anyhow!("The request is not authenticated"))
+        })?;
+    let db_pool = req.app_data::<Data<PgPool>>().cloned().ok_or_else(|| {
+        AppError::unexpected(anyhow::anyhow!("The database pool is not registered"))
+    })?;
+    let settings = req
+        .app_data::<Data<IdempotencySettings>>()
+        .cloned()
+        .ok_or_else(|| {
+            AppError::unexpected(anyhow::anyhow!(
+                "The idempotency settings are not registered"
+            ))
+        })?;
+
+    // The `Bytes` extractor would apply its own limit rather than the one of the route
+    let limit = body_limit(&req);
+    let body = req
+        .extract::<web::Payload>()
+        .await?
+        .to_bytes_limited(limit)
+        .await
+        .map_err(|_| {
+            AppError::PayloadTooLarge(format!("The request body is larger than {limit} bytes"))
+        })??;
+    let idempotency_key = submitted_key(&req, &body)
+        .and_then(|key| {
+            IdempotencyKey::try_from(key).map_err(|e| IdempotencyError::InvalidKey(e.to_string()))
+        })
+        .map_err(AppError::from)?;
+    let fingerprint = request_fingerprint(&req, &body);
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body);
+    req.set_payload(payload.into());
+
+    let transaction = match try_processing(
+        &db_pool,
+        &idempotency_key,
+        *user_id,
+        &fingerprint,
+        &settings,
+    )
+    .await
+    .map_err(AppError::from)?
+    {
+        NextAction::StartProcessing(transaction) => *transaction,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            if let Some(on_replay) = on_replay {
+                on_replay();
+            }
+            let (request, _) = req.into_parts();
+            return Ok(ServiceResponse::new(request, saved_response).map_into_boxed_body());
+        }
+    };
+    let transaction = IdempotentTransaction(Rc::new(Mutex::new(Some(transaction))));
+    req.extensions_mut().insert(transaction.clone());
+
+    let response = next.call(req).await?.map_into_boxed_body();
+    let transaction = transaction
+        .0
+        .lock()
+        .await
+        .take()
+        .expect("The transaction is only taken back once, after the handler");
+    // Failed requests are rolled back rather than saved, so they can be retried
+    if response.status().is_client_error() || response.status().is_server_error() {
+        return Ok(response);
+    }
+    let (request, response) = response.into_parts();
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(AppError::unexpected)?;
+    Ok(ServiceResponse::new(request, response))
+}
+
+// The key is taken from the header first, then from the url-encoded form body.
+fn submitted_key(req: &ServiceRequest, body: &Bytes) -> Result<String, IdempotencyError> {
+    if let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        return key
+            .to_str()
+            .map(|key| key.to_string())
+            .map_err(|_| IdempotencyError::InvalidKey("Invalid idempotency key".to_string()));
+    }
+    if !has_content_type(req, "application/x-www-form-urlencoded") {
+        return Err(IdempotencyError::MissingKey);
+    }
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()
+        .and_then(|fields| {
+            fields
+                .into_iter()
+                .find(|(name, _)| name == IDEMPOTENCY_KEY_FORM_FIELD)
+                .map(|(_, value)| value)
+        })
+        .ok_or(IdempotencyError::MissingKey)
+}
+
+// Hash of what the request asks for: its method, path and body. Replays have to match it.
+// Forms carry the CSRF token of the session, which changes when the user logs in again:
+// it is left out so that a retry from a new session still matches.
+fn request_fingerprint(req: &ServiceRequest, body: &Bytes) -> String {
+    let form_fields = has_content_type(req, "application/x-www-form-urlencoded")
+        .then(|| serde_urlencoded::from_bytes::<Vec<(String, String)>>(body).ok())
+        .flatten();
+    let body = match form_fields {
+        Some(fields) => {
+            let fields: Vec<_> = fields
+                .into_iter()
+                .filter(|(name, _)| name != CSRF_FORM_FIELD)
+                .collect();
+            Bytes::from(serde_urlencoded::to_string(fields).unwrap_or_default())
+        }
+        None => body.clone(),
+    };
+    let mut hasher = Sha256::new();
+    hasher.update(req.method().as_str());
+    hasher.update(b"\n");
+    hasher.update(req.path());
+    hasher.update(b"\n");
+    hasher.update(&body);
+    format!("{:x}", hasher.finalize())
+}
+
+// Forms are the only bodies read with the form extractor, everything else is JSON
+fn body_limit(req: &ServiceRequest) -> usize {
+    if has_content_type(req, "application/x-www-form-urlencoded") {
+        FORM_BODY_LIMIT
+    } else {
+        JSON_BODY_LIMIT
+    }
+}
+
+fn has_content_type(req: &ServiceRequest, content_type: &str) -> bool {
+    req.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with(content_type))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::ContentType;
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn fingerprint(request: TestRequest, body: &'static str) -> String {
+        request_fingerprint(&request.to_srv_request(), &Bytes::from(body))
+    }
+
+    #[test]
+    fn fingerprints_depend_on_method_path_and_body() {
+        let reference = fingerprint(TestRequest::post().uri("/api/v1/issues"), "{}");
+        assert_eq!(
+            reference,
+            fingerprint(TestRequest::post().uri("/api/v1/issues"), "{}")
+        );
+        assert_ne!(
+            reference,
+            fingerprint(TestRequest::put().uri("/api/v1/issues"), "{}")
+        );
+        assert_ne!(
+            reference,
+            fingerprint(TestRequest::post().uri("/admin/newsletters"), "{}")
+        );
+        assert_ne!(
+            reference,
+            fingerprint(TestRequest::post().uri("/api/v1/issues"), "{ }")
+        );
+    }
+
+    #[test]
+    fn fingerprints_of_forms_ignore_the_csrf_token() {
+        let form = || {
+            TestRequest::post()
+                .uri("/admin/newsletters")
+                .insert_header(ContentType::form_url_encoded())
+        };
+        assert_eq!(
+            fingerprint(form(), "title=Hello&csrf_token=first"),
+            fingerprint(form(), "title=Hello&csrf_token=second")
+        );
+        assert_ne!(
+            fingerprint(form(), "title=Hello&csrf_token=first"),
+            fingerprint(form(), "title=Goodbye&csrf_token=first")
+        );
+    }
+}
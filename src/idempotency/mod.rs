@@ -1,7 +1,12 @@
+mod expiry;
 mod key;
+mod middleware;
 mod persistence;
 
+pub use expiry::delete_expired_keys;
+pub use expiry::run_cleanup_until_stopped;
 pub use key::IdempotencyKey;
+pub use middleware::*;
 pub use persistence::save_response;
 pub use persistence::try_processing;
 pub use persistence::NextAction;
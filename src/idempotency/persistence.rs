@@ -1,8 +1,15 @@
-use crate::idempotency::IdempotencyKey;
+use crate::config::IdempotencySettings;
+use crate::idempotency::{IdempotencyError, IdempotencyKey};
 use actix_web::http::StatusCode;
 use actix_web::HttpResponse;
+use anyhow::Context;
 use sqlx::postgres::{PgHasArrayType, PgTypeInfo};
 use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::time::Duration;
+
+// Postgres error code raised when `lock_timeout` expires
+const LOCK_NOT_AVAILABLE: &str = "55P03";
+const IN_PROGRESS_RETRY_AFTER: Duration = Duration::from_secs(1);
 
 pub async fn save_response(
     mut transaction: Transaction<'static, Postgres>,
@@ -51,49 +58,107 @@ pub async fn save_response(
     Ok(http_response)
 }
 
+// Keys older than the TTL are treated as new: their saved response is discarded.
 pub async fn try_processing(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: uuid::Uuid,
-) -> Result<NextAction, anyhow::Error> {
-    let mut transaction = db_pool.begin().await?;
+    request_fingerprint: &str,
+    settings: &IdempotencySettings,
+) -> Result<NextAction, IdempotencyError> {
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to begin transaction")?;
+    // A request with the same key that is still in flight holds a lock on the row
+    // until it saves its response: wait for it, but not forever
+    transaction
+        .execute(sqlx::query!(
+            "SELECT set_config('lock_timeout', $1, true)",
+            format!("{}ms", settings.in_flight_wait().as_millis())
+        ))
+        .await
+        .context("Failed to set lock timeout")?;
     let query = sqlx::query!(
         r#"
         INSERT INTO idempotency (
             user_id,
             idempotency_key,
+            request_fingerprint,
             created_at
         )
-        VALUES ($1, $2, now())
-        ON CONFLICT DO NOTHING
+        VALUES ($1, $2, $4, now())
+        ON CONFLICT (user_id, idempotency_key) DO UPDATE SET
+            request_fingerprint = $4,
+            created_at = now(),
+            response_status_code = NULL,
+            response_headers = NULL,
+            response_body = NULL
+        WHERE idempotency.created_at < now() - make_interval(secs => $3)
         "#,
         user_id,
         idempotency_key.as_ref(),
+        settings.ttl().as_secs_f64(),
+        request_fingerprint,
     );
-    let num_inserted_rows = transaction.execute(query).await?.rows_affected();
-
-    if num_inserted_rows == 0 {
-        let saved_response = get_saved_response(db_pool, idempotency_key, user_id)
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+    let num_inserted_rows = match transaction.execute(query).await {
+        Ok(outcome) => outcome.rows_affected(),
+        Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some(LOCK_NOT_AVAILABLE) => {
+            return Err(IdempotencyError::InProgress {
+                retry_after: IN_PROGRESS_RETRY_AFTER,
+            });
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to claim the key")
+                .into())
+        }
+    };
+    // The timeout is only meant for the wait above, not for the rest of the request
+    transaction
+        .execute("SET LOCAL lock_timeout TO DEFAULT")
+        .await
+        .context("Failed to reset lock timeout")?;
 
-        Ok(NextAction::ReturnSavedResponse(saved_response))
-    } else {
-        Ok(NextAction::StartProcessing(transaction))
+    if num_inserted_rows > 0 {
+        return Ok(NextAction::StartProcessing(Box::new(transaction)));
+    }
+    let saved = get_saved_response(db_pool, idempotency_key, user_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("The key has been deleted while we were using it"))?;
+    // Keys saved before fingerprints were recorded match any request
+    if saved
+        .request_fingerprint
+        .is_some_and(|fingerprint| fingerprint != request_fingerprint)
+    {
+        return Err(IdempotencyError::KeyReused);
+    }
+    match saved.response {
+        Some(saved_response) => Ok(NextAction::ReturnSavedResponse(saved_response)),
+        // The row exists but no response has been saved yet
+        None => Err(IdempotencyError::InProgress {
+            retry_after: IN_PROGRESS_RETRY_AFTER,
+        }),
     }
 }
 
+struct SavedResponse {
+    request_fingerprint: Option<String>,
+    response: Option<HttpResponse>,
+}
+
 async fn get_saved_response(
     db_pool: &PgPool,
     idempotency_key: &IdempotencyKey,
     user_id: uuid::Uuid,
-) -> Result<Option<HttpResponse>, anyhow::Error> {
+) -> Result<Option<SavedResponse>, anyhow::Error> {
     let saved_response = sqlx::query!(
         r#"
         SELECT
-            response_status_code AS "response_status_code!",
-            response_headers AS "response_headers!: Vec<HeaderPairRecord>",
-            response_body AS "response_body!"
+            request_fingerprint,
+            response_status_code,
+            response_headers AS "response_headers: Vec<HeaderPairRecord>",
+            response_body
         FROM idempotency
         WHERE
             user_id = $1 AND
@@ -105,20 +170,32 @@ async fn get_saved_response(
     .fetch_optional(db_pool)
     .await?;
 
-    if let Some(row) = saved_response {
-        let status_code = StatusCode::from_u16(row.response_status_code.try_into()?)?;
-        let mut response = HttpResponse::build(status_code);
-        for header in row.response_headers {
-            response.append_header((header.name, header.value));
+    let Some(row) = saved_response else {
+        return Ok(None);
+    };
+    let response = match (
+        row.response_status_code,
+        row.response_headers,
+        row.response_body,
+    ) {
+        (Some(status_code), Some(headers), Some(body)) => {
+            let status_code = StatusCode::from_u16(status_code.try_into()?)?;
+            let mut response = HttpResponse::build(status_code);
+            for header in headers {
+                response.append_header((header.name, header.value));
+            }
+            Some(response.body(body))
         }
-        Ok(Some(response.body(row.response_body)))
-    } else {
-        Ok(None)
-    }
+        _ => None,
+    };
+    Ok(Some(SavedResponse {
+        request_fingerprint: row.request_fingerprint,
+        response,
+    }))
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    StartProcessing(Box<Transaction<'static, Postgres>>),
     ReturnSavedResponse(HttpResponse),
 }
 
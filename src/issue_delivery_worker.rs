@@ -1,20 +1,30 @@
 use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use metrics::{counter, histogram};
 use sqlx::{PgPool, Postgres, Transaction};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::field::display;
 use tracing::Span;
 use uuid::Uuid;
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+// Target of the worker's logs, see `LogFilter::with_foreign_targets`
+pub const LOG_TARGET: &str = module_path!();
+// Name of the worker in the `worker_heartbeat` table
+pub const WORKER_NAME: &str = "issue_delivery_worker";
+// How often the worker refreshes its heartbeat, whether or not the queue is busy
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
 pub async fn run_worker_until_stopped(
     config: crate::config::Settings,
+    shutdown: CancellationToken,
 ) -> Result<(), anyhow::Error> {
     let db_pool = crate::startup::get_db_pool(&config.database);
     let email_client = config.email_client.client();
 
-    worker_loop(db_pool, email_client).await
+    worker_loop(db_pool, email_client, shutdown).await
 }
 
 pub enum ExecutionOutcome {
@@ -22,14 +32,37 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
-async fn worker_loop(db_pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&db_pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
-            Ok(ExecutionOutcome::TaskCompleted) => {}
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+// Cancelling `shutdown` never interrupts a delivery: the worker stops before dequeuing
+// its next task, once the current one is committed.
+pub async fn worker_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    shutdown: CancellationToken,
+) -> Result<(), anyhow::Error> {
+    let mut last_heartbeat: Option<Instant> = None;
+    while !shutdown.is_cancelled() {
+        // Draining the queue loops without pausing, the heartbeat must not be written every time
+        if last_heartbeat.is_none_or(|at| at.elapsed() >= HEARTBEAT_INTERVAL) {
+            match record_heartbeat(&db_pool).await {
+                Ok(()) => last_heartbeat = Some(Instant::now()),
+                Err(e) => tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "Failed to record the worker heartbeat",
+                ),
+            }
+        }
+        let pause = match try_execute_task(&db_pool, &email_client).await {
+            Ok(ExecutionOutcome::EmptyQueue) => Duration::from_secs(10),
+            Ok(ExecutionOutcome::TaskCompleted) => continue,
+            Err(_) => Duration::from_secs(1),
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(pause) => {}
+            _ = shutdown.cancelled() => {}
         }
     }
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -44,39 +77,54 @@ pub async fn try_execute_task(
     db_pool: &PgPool,
     email_client: &EmailClient,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    if let Some((transaction, issue_id, email)) = dequeue_task(db_pool).await? {
+    if let Some((mut transaction, issue_id, email)) = dequeue_task(db_pool).await? {
         Span::current()
-            .record("newsletter_issue_id", &display(issue_id))
-            .record("subscriber_email", &display(&email));
+            .record("newsletter_issue_id", display(issue_id))
+            .record("subscriber_email", display(&email));
 
         // Send email
-        match SubscriberEmail::parse(email.clone()) {
+        let failure = match SubscriberEmail::parse(email.clone()) {
             Ok(email) => {
                 let issue = get_issue(db_pool, issue_id).await?;
-                if let Err(e) = email_client
+                let start = Instant::now();
+                let outcome = email_client
                     .send_email(
                         &email,
                         &issue.title,
                         &issue.html_content,
                         &issue.text_content,
                     )
-                    .await
-                {
-                    tracing::error!(
-                        error.cause_chain = ?e,
-                        error.message = %e,
-                        "Failed to deliver issue to a confirmed subscriber. Skipping.",
-                    );
+                    .await;
+                histogram!("email_delivery_duration_seconds").record(start.elapsed().as_secs_f64());
+                match outcome {
+                    Ok(()) => {
+                        counter!("email_deliveries_total", "outcome" => "success").increment(1);
+                        None
+                    }
+                    Err(e) => {
+                        counter!("email_deliveries_total", "outcome" => "failure").increment(1);
+                        tracing::error!(
+                            error.cause_chain = ?e,
+                            error.message = %e,
+                            "Failed to deliver issue to a confirmed subscriber. Skipping.",
+                        );
+                        Some(e.to_string())
+                    }
                 }
             }
             Err(e) => {
+                counter!("email_deliveries_total", "outcome" => "invalid_address").increment(1);
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
                     "Skipping a confirmed subscriber. \
                     Their stored contact details are invalid",
                 );
+                Some(e.to_string())
             }
+        };
+        if let Some(reason) = failure {
+            record_failure(&mut transaction, issue_id, &email, &reason).await?;
         }
         delete_task(transaction, issue_id, &email).await?;
         Ok(ExecutionOutcome::TaskCompleted)
@@ -85,6 +133,47 @@ pub async fn try_execute_task(
     }
 }
 
+pub struct QueueStats {
+    pub issue_id: Uuid,
+    pub title: String,
+    pub pending_deliveries: i64,
+}
+
+// Deliveries waiting in the queue, by issue, oldest issue first
+pub async fn queue_stats(db_pool: &PgPool) -> Result<Vec<QueueStats>, anyhow::Error> {
+    let stats = sqlx::query_as!(
+        QueueStats,
+        r#"
+        SELECT
+            i.newsletter_issue_id AS issue_id,
+            i.title,
+            COUNT(*) AS "pending_deliveries!"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issue i ON i.newsletter_issue_id = q.newsletter_issue_id
+        GROUP BY i.newsletter_issue_id, i.title, i.published_at
+        ORDER BY i.published_at
+        "#
+    )
+    .fetch_all(db_pool)
+    .await?;
+    Ok(stats)
+}
+
+// Lets the readiness probe tell that the worker is still polling the queue
+pub async fn record_heartbeat(db_pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO worker_heartbeat (worker, last_seen_at)
+        VALUES ($1, now())
+        ON CONFLICT (worker) DO UPDATE SET last_seen_at = now()
+        "#,
+        WORKER_NAME
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     db_pool: &PgPool,
@@ -112,6 +201,30 @@ async fn dequeue_task(
     }
 }
 
+// Failed deliveries are not retried, but they are reported with the issue's status
+#[tracing::instrument(skip_all)]
+async fn record_failure(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    subscriber_email: &str,
+    reason: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failure (newsletter_issue_id, subscriber_email, reason, failed_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET reason = EXCLUDED.reason, failed_at = EXCLUDED.failed_at
+        "#,
+        issue_id,
+        subscriber_email,
+        reason
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn delete_task(
     mut transaction: PgTransaction,
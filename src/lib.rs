@@ -1,11 +1,21 @@
 pub mod authentication;
+pub mod cli;
+pub mod client_ip;
+pub mod clock;
 pub mod config;
 pub mod domain;
 pub mod email_client;
+pub mod error;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod monitoring;
+pub mod rate_limit;
 pub mod routes;
+pub mod security_headers;
+pub mod session_registry;
 pub mod session_state;
 pub mod startup;
+pub mod subscription_protection;
 pub mod telemetry;
+pub mod tls;
 pub mod utils;
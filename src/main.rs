@@ -1,28 +1,201 @@
 use std::fmt::{Debug, Display};
-use tokio::task::JoinError;
-use zero2prod::config;
-use zero2prod::startup::Application;
-use zero2prod::telemetry::{get_subscriber, init_subscriber};
+use std::future::Future;
+use std::time::Duration;
+
+use clap::Parser;
+use secrecy::Secret;
+use tokio::task::{JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use zero2prod::cli::{self, Cli, Command, QueueCommand, SubscribersCommand};
+use zero2prod::config::{self, Settings};
+use zero2prod::issue_delivery_worker;
+use zero2prod::startup::{get_db_pool, Application, MetricsServer};
+use zero2prod::telemetry::{get_subscriber, init_subscriber, otlp_tracer, shutdown_tracing};
+
+type TaskOutcome = (&'static str, Result<Result<(), anyhow::Error>, JoinError>);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("zero2prod", "info", std::io::stdout);
-    init_subscriber(subscriber);
+    let cli = Cli::parse();
+    let config = config::get_config(cli.config.as_deref())?;
+
+    let tracer = config
+        .telemetry
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| otlp_tracer(&config.telemetry.service_name, endpoint))
+        .transpose()?;
+    // One-off commands print their outcome on stdout, keep the logs out of the way
+    let sink = match cli.command {
+        None | Some(Command::Serve) | Some(Command::Worker) => BoxMakeWriter::new(std::io::stdout),
+        Some(_) => BoxMakeWriter::new(std::io::stderr),
+    };
+    let (subscriber, log_filter) = get_subscriber("zero2prod", "info", sink, tracer);
+    // The admin area can only change the logs of its own process
+    let log_filter = match cli.command {
+        Some(Command::Serve) => {
+            log_filter.with_foreign_targets(&[issue_delivery_worker::LOG_TARGET])
+        }
+        _ => log_filter,
+    };
+    init_subscriber(subscriber, log_filter);
+    if tracing::enabled!(tracing::Level::DEBUG) {
+        // Secrets are redacted when the settings are serialized
+        tracing::debug!(
+            config = %serde_json::to_string(&config)?,
+            "Loaded the configuration"
+        );
+    }
+
+    let shutdown = CancellationToken::new();
+    let shutdown_timeout = config.application.shutdown_timeout();
+    let outcome = match cli.command {
+        None => {
+            let mut tasks = JoinSet::new();
+            spawn_api(&mut tasks, config.clone(), true, &shutdown).await?;
+            spawn_worker(&mut tasks, config, &shutdown);
+            run_until_shutdown(tasks, shutdown, shutdown_timeout).await;
+            Ok(())
+        }
+        Some(Command::Serve) => {
+            let mut tasks = JoinSet::new();
+            spawn_api(&mut tasks, config, false, &shutdown).await?;
+            run_until_shutdown(tasks, shutdown, shutdown_timeout).await;
+            Ok(())
+        }
+        Some(Command::Worker) => {
+            let mut tasks = JoinSet::new();
+            spawn_worker_metrics(&mut tasks, &config, &shutdown)?;
+            spawn_worker(&mut tasks, config, &shutdown);
+            run_until_shutdown(tasks, shutdown, shutdown_timeout).await;
+            Ok(())
+        }
+        Some(Command::Migrate) => cli::migrate(&config).await,
+        Some(Command::CreateAdmin {
+            username,
+            password,
+            role,
+        }) => cli::create_admin(&config, &username, Secret::new(password), role).await,
+        Some(Command::SendTestEmail { recipient }) => {
+            cli::send_test_email(&config, recipient).await
+        }
+        Some(Command::Queue {
+            command: QueueCommand::Stats,
+        }) => cli::print_queue_stats(&config).await,
+        Some(Command::Subscribers {
+            command: SubscribersCommand::Merged,
+        }) => cli::print_merged_subscriptions(&config).await,
+    };
+
+    shutdown_tracing();
+    outcome
+}
+
+// The API runs along with the cleanup of its idempotency keys
+async fn spawn_api(
+    tasks: &mut JoinSet<TaskOutcome>,
+    config: Settings,
+    with_worker: bool,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<()> {
+    let application = if with_worker {
+        Application::build(config.clone()).await?
+    } else {
+        Application::build_without_worker(config.clone()).await?
+    };
+    let api_shutdown = shutdown.clone();
+    spawn_named(tasks, "API", async move {
+        application
+            .run_until_stopped(api_shutdown)
+            .await
+            .map_err(Into::into)
+    });
+    spawn_named(
+        tasks,
+        "Idempotency cleanup",
+        zero2prod::idempotency::run_cleanup_until_stopped(config, shutdown.clone()),
+    );
+    Ok(())
+}
 
-    let config = config::get_config().expect("Failed to read config file");
+fn spawn_worker(tasks: &mut JoinSet<TaskOutcome>, config: Settings, shutdown: &CancellationToken) {
+    spawn_named(
+        tasks,
+        "Background worker",
+        issue_delivery_worker::run_worker_until_stopped(config, shutdown.clone()),
+    );
+}
 
-    let application = Application::build(config.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(zero2prod::issue_delivery_worker::run_worker_until_stopped(
-        config,
-    ));
+// Without the API in the process, the worker serves the metrics it records itself
+fn spawn_worker_metrics(
+    tasks: &mut JoinSet<TaskOutcome>,
+    config: &Settings,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<()> {
+    let db_pool = get_db_pool(&config.database);
+    if let Some(metrics) = MetricsServer::build(&config.application, db_pool)? {
+        let metrics_shutdown = shutdown.clone();
+        spawn_named(tasks, "Metrics", async move {
+            metrics
+                .run_until_stopped(metrics_shutdown)
+                .await
+                .map_err(Into::into)
+        });
+    }
+    Ok(())
+}
+
+// The task is spawned on its own so that a panic is reported under its name
+fn spawn_named<F>(tasks: &mut JoinSet<TaskOutcome>, name: &'static str, task: F)
+where
+    F: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+    let handle = tokio::spawn(task);
+    tasks.spawn(async move { (name, handle.await) });
+}
 
+// The process shuts down on SIGTERM, on Ctrl-C or as soon as one of its tasks exits.
+// The other tasks are then asked to stop and given `shutdown_timeout` to wind down.
+async fn run_until_shutdown(
+    mut tasks: JoinSet<TaskOutcome>,
+    shutdown: CancellationToken,
+    shutdown_timeout: Duration,
+) {
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        _ = shutdown_signal() => tracing::info!("Received a shutdown signal"),
+        Some(Ok((name, outcome))) = tasks.join_next() => report_exit(name, outcome),
     }
+    shutdown.cancel();
 
-    Ok(())
+    let drain = tokio::time::timeout(shutdown_timeout, async {
+        while let Some(Ok((name, outcome))) = tasks.join_next().await {
+            report_exit(name, outcome);
+        }
+    });
+    if drain.await.is_err() {
+        tracing::error!(
+            "{} task(s) did not stop within {:?} and were abandoned",
+            tasks.len(),
+            shutdown_timeout
+        );
+    }
+}
+
+async fn shutdown_signal() {
+    let terminate = async {
+        #[cfg(unix)]
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+        #[cfg(not(unix))]
+        std::future::pending::<()>().await;
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
+    }
 }
 
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
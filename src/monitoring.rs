@@ -0,0 +1,97 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web_lab::middleware::Next;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+// Upper bounds, in seconds, of the buckets of every latency histogram
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// Metrics are recorded with the `metrics` macros from anywhere in the process (HTTP
+// middleware, delivery worker, email client) into a single Prometheus recorder.
+// It is installed on first use, which lets several applications share a process in tests.
+pub fn prometheus_handle() -> PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    HANDLE
+        .get_or_init(|| {
+            let handle = PrometheusBuilder::new()
+                .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)
+                .expect("Failed to set histogram buckets")
+                .install_recorder()
+                .expect("Failed to install the Prometheus recorder");
+            describe_metrics();
+            handle
+        })
+        .clone()
+}
+
+fn describe_metrics() {
+    describe_counter!("http_requests_total", "HTTP requests by route and status");
+    describe_histogram!(
+        "http_request_duration_seconds",
+        Unit::Seconds,
+        "Time taken to answer HTTP requests"
+    );
+    describe_gauge!(
+        "issue_delivery_queue_depth",
+        "Newsletter deliveries waiting to be attempted"
+    );
+    describe_counter!(
+        "email_deliveries_total",
+        "Newsletter deliveries attempted by the worker, by outcome"
+    );
+    describe_histogram!(
+        "email_delivery_duration_seconds",
+        Unit::Seconds,
+        "Time taken to hand a newsletter issue over to the email provider"
+    );
+    describe_counter!(
+        "email_client_responses_total",
+        "Responses of the email provider by status code, `error` if there was none"
+    );
+    describe_gauge!(
+        "db_pool_connections",
+        "Postgres connections held by the pool, by state"
+    );
+    describe_gauge!(
+        "db_pool_max_connections",
+        "Maximum number of connections of the Postgres pool"
+    );
+}
+
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    // Label by route pattern rather than path to keep the number of series bounded
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.call(req).await;
+    let status = match &response {
+        Ok(response) => response.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    counter!(
+        "http_requests_total",
+        "method" => method.clone(),
+        "route" => route.clone(),
+        "status" => status.as_u16().to_string()
+    )
+    .increment(1);
+    histogram!(
+        "http_request_duration_seconds",
+        "method" => method,
+        "route" => route
+    )
+    .record(start.elapsed().as_secs_f64());
+    response
+}
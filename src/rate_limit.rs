@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+
+// Fixed-window counters stored in Redis.
+// A counter starts its window on the first hit and disappears when the window expires.
+#[derive(Clone)]
+pub struct RateLimiter {
+    redis: ConnectionManager,
+    key_prefix: String,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub max_hits: u64,
+    pub window: Duration,
+}
+
+pub struct Counter {
+    pub key: String,
+    pub hits: u64,
+    pub expires_in: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(redis: ConnectionManager, key_prefix: String) -> Self {
+        Self { redis, key_prefix }
+    }
+
+    fn redis_key(&self, key: &str) -> String {
+        format!("{}:{}", self.key_prefix, key)
+    }
+
+    // Counts a hit and returns how long the caller has to wait if it went over the limit.
+    // The decision comes from the incremented counter, so concurrent callers cannot
+    // all slip under the limit.
+    pub async fn hit(&self, key: &str, limit: Limit) -> Result<Option<Duration>, anyhow::Error> {
+        let (hits, ttl) = self.increment(key, 1, limit).await?;
+        Ok((hits > limit.max_hits as i64).then(|| Duration::from_secs(ttl.max(1) as u64)))
+    }
+
+    // Takes back a hit that turned out not to count, like a successful attempt.
+    pub async fn undo_hit(&self, key: &str, limit: Limit) -> Result<(), anyhow::Error> {
+        self.increment(key, -1, limit).await?;
+        Ok(())
+    }
+
+    // The counter and its expiry are updated in a single transaction: a counter must never
+    // be left without an expiry, or whatever it counts would stay locked out for good.
+    async fn increment(
+        &self,
+        key: &str,
+        delta: i64,
+        limit: Limit,
+    ) -> Result<(i64, i64), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let redis_key = self.redis_key(key);
+        let (hits, ttl): (i64, i64) = redis::pipe()
+            .atomic()
+            .incr(&redis_key, delta)
+            // NX keeps the expiry of an existing window
+            .cmd("EXPIRE")
+            .arg(&redis_key)
+            .arg(limit.window.as_secs())
+            .arg("NX")
+            .ignore()
+            .ttl(&redis_key)
+            .query_async(&mut redis)
+            .await
+            .context("Failed to update rate limit counter")?;
+        Ok((hits, ttl))
+    }
+
+    pub async fn reset(&self, key: &str) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        redis
+            .del::<_, ()>(self.redis_key(key))
+            .await
+            .context("Failed to reset rate limit counter")?;
+        Ok(())
+    }
+
+    // Counters whose key starts with `key_prefix`, keys are returned without our namespace.
+    pub async fn counters(&self, key_prefix: &str) -> Result<Vec<Counter>, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let namespace = self.redis_key("");
+        let keys: Vec<String> = {
+            let mut iter = redis
+                .scan_match::<_, String>(format!("{}{}*", namespace, key_prefix))
+                .await
+                .context("Failed to scan rate limit counters")?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+
+        let mut counters = Vec::with_capacity(keys.len());
+        for redis_key in keys {
+            // Undone hits can take a counter below zero
+            let hits: Option<i64> = redis
+                .get(&redis_key)
+                .await
+                .context("Failed to read rate limit counter")?;
+            let ttl: i64 = redis
+                .ttl(&redis_key)
+                .await
+                .context("Failed to read rate limit counter expiry")?;
+            if let (Some(hits), true) = (hits, ttl > 0) {
+                counters.push(Counter {
+                    key: redis_key[namespace.len()..].to_string(),
+                    hits: hits.max(0) as u64,
+                    expires_in: Duration::from_secs(ttl as u64),
+                });
+            }
+        }
+        Ok(counters)
+    }
+}
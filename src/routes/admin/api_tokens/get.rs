@@ -0,0 +1,98 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+
+use crate::authentication;
+use crate::authentication::UserId;
+use crate::error::AppError;
+use crate::utils;
+
+#[utoipa::path(
+    get,
+    path = "/admin/api-tokens",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The API tokens of the user", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in")
+    )
+)]
+pub async fn api_tokens(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let tokens = authentication::list_api_tokens(user_id.0, &db_pool)
+        .await
+        .map_err(AppError::unexpected)?;
+    let mut tokens_html = String::new();
+    for token in &tokens {
+        let last_used = token
+            .last_used_at
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|| "Never".to_string());
+        writeln!(
+            tokens_html,
+            r#"
+                    <tr>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{last_used}</td>
+                        <td>
+                            <form action="/admin/api-tokens/revoke" method="post">
+                                <input hidden type="text" name="api_token_id" value="{}">
+                                <button type="submit">Revoke</button>
+                            </form>
+                        </td>
+                    </tr>"#,
+            utils::escape_html(&token.name),
+            token.created_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            token.api_token_id,
+        )
+        .unwrap();
+    }
+    if tokens.is_empty() {
+        tokens_html.push_str(r#"<tr><td colspan="4">You have no API tokens.</td></tr>"#);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>API tokens</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Name</th>
+                        <th>Created</th>
+                        <th>Last used</th>
+                        <th></th>
+                    </tr>
+                    {tokens_html}
+                </table>
+                <form action="/admin/api-tokens" method="post">
+                    <label>Name
+                        <input type="text" placeholder="What is this token for?" name="name">
+                    </label>
+                    <button type="submit">Create token</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,5 @@
+pub use get::api_tokens;
+pub use post::{create_api_token, revoke_api_token};
+
+pub(crate) mod get;
+pub(crate) mod post;
@@ -0,0 +1,107 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::authentication;
+use crate::authentication::UserId;
+use crate::clock::Clock;
+use crate::error::AppError;
+use crate::utils;
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateFormData {
+    name: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RevokeFormData {
+    api_token_id: Uuid,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/api-tokens",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(CreateFormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/api-tokens if the name is invalid"),
+        (status = 200, description = "Shows the new token, the only time it is visible", content_type = "text/html", body = String),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn create_api_token(
+    form: web::Form<CreateFormData>,
+    db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        FlashMessage::error("The token name must be between 1 and 100 characters long").send();
+        return Ok(utils::see_other("/admin/api-tokens"));
+    }
+
+    let token = authentication::create_api_token(user_id.0, name, clock.now(), &db_pool)
+        .await
+        .map_err(AppError::unexpected)?;
+
+    // Tokens are only stored hashed, so this is the one time we can show it
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>New API token</title>
+            </head>
+            <body>
+                <p>Your new API token <b>{}</b> is:</p>
+                <p><code>{}</code></p>
+                <p>Copy it now, it will not be shown again.
+                Send it as <code>Authorization: Bearer &lt;token&gt;</code>.</p>
+                <p><a href="/admin/api-tokens">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+            utils::escape_html(name),
+            token.expose_secret()
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/api-tokens/revoke",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(RevokeFormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/api-tokens with the outcome as a flash message"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn revoke_api_token(
+    form: web::Form<RevokeFormData>,
+    db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked =
+        authentication::revoke_api_token(user_id.0, form.0.api_token_id, clock.now(), &db_pool)
+            .await
+            .map_err(AppError::unexpected)?;
+
+    if revoked {
+        FlashMessage::info("The API token has been revoked").send();
+    } else {
+        FlashMessage::error("The API token does not exist or has already been revoked").send();
+    }
+    Ok(utils::see_other("/admin/api-tokens"))
+}
@@ -1,19 +1,28 @@
 use crate::authentication::UserId;
+use crate::error::AppError;
 use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::utils;
-
+#[utoipa::path(
+    get,
+    path = "/admin/dashboard",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The admin dashboard", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in")
+    )
+)]
 pub async fn admin_dashboard(
     db_pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let username = fetch_username(&user_id.0, &db_pool)
         .await
-        .map_err(utils::error_500)?;
+        .map_err(AppError::unexpected)?;
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -31,6 +40,11 @@ pub async fn admin_dashboard(
                 <ol>
                     <li><a href="/admin/password">Change password</a></li>
                     <li><a href="/admin/newsletters">Send a newsletter</a></li>
+                    <li><a href="/admin/security">Two-factor authentication</a></li>
+                    <li><a href="/admin/sessions">Active sessions</a></li>
+                    <li><a href="/admin/api-tokens">API tokens</a></li>
+                    <li><a href="/admin/lockouts">Login lockouts</a></li>
+                    <li><a href="/admin/log-level">Log level</a></li>
                     <li>
                         <form name="logoutForm" action="/admin/logout" method="post">
                             <input type="submit" value="Logout">
@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::authentication::LoginThrottle;
+use crate::error::AppError;
+use crate::utils;
+
+#[utoipa::path(
+    get,
+    path = "/admin/lockouts",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "Usernames and IPs locked out after too many failed logins", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in"),
+        (status = 403, description = "Only owners can manage lockouts")
+    )
+)]
+pub async fn lockouts(
+    flash_messages: IncomingFlashMessages,
+    throttle: web::Data<LoginThrottle>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let lockouts = throttle.lockouts().await.map_err(AppError::unexpected)?;
+    let mut lockouts_html = String::new();
+    for lockout in &lockouts {
+        let kind = lockout.kind.as_str();
+        let subject = utils::escape_html(&lockout.subject);
+        writeln!(
+            lockouts_html,
+            r#"
+                    <tr>
+                        <td>{kind}</td>
+                        <td>{subject}</td>
+                        <td>{}</td>
+                        <td>{}s</td>
+                        <td>
+                            <form action="/admin/lockouts/unlock" method="post">
+                                <input hidden type="text" name="kind" value="{kind}">
+                                <input hidden type="text" name="subject" value="{subject}">
+                                <button type="submit">Unlock</button>
+                            </form>
+                        </td>
+                    </tr>"#,
+            lockout.failures,
+            lockout.retry_after.as_secs(),
+        )
+        .unwrap();
+    }
+    if lockouts.is_empty() {
+        lockouts_html.push_str(r#"<tr><td colspan="5">Nobody is locked out.</td></tr>"#);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Login lockouts</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Locked by</th>
+                        <th>Username or IP</th>
+                        <th>Failed attempts</th>
+                        <th>Expires in</th>
+                        <th></th>
+                    </tr>
+                    {lockouts_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,5 @@
+pub use get::lockouts;
+pub use post::unlock;
+
+pub(crate) mod get;
+pub(crate) mod post;
@@ -0,0 +1,44 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::authentication::{LockoutKind, LoginThrottle};
+use crate::error::AppError;
+use crate::utils;
+
+#[derive(Deserialize, ToSchema)]
+pub struct FormData {
+    kind: String,
+    subject: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/lockouts/unlock",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/lockouts once the lockout is lifted"),
+        (status = 400, description = "Unknown lockout kind"),
+        (status = 403, description = "Only owners can manage lockouts, and the CSRF token must be valid")
+    )
+)]
+pub async fn unlock(
+    form: web::Form<FormData>,
+    throttle: web::Data<LoginThrottle>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let kind = LockoutKind::try_from(form.0.kind).map_err(AppError::BadRequest)?;
+    throttle
+        .unlock(kind, &form.0.subject)
+        .await
+        .map_err(AppError::unexpected)?;
+
+    FlashMessage::info(format!(
+        "{} has been unlocked",
+        utils::escape_html(&form.0.subject)
+    ))
+    .send();
+    Ok(utils::see_other("/admin/lockouts"))
+}
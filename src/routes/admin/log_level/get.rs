@@ -0,0 +1,91 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::error::AppError;
+use crate::{telemetry, utils};
+
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The current log filter directives and a form to change them", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in"),
+        (status = 403, description = "Only owners can change the log level")
+    )
+)]
+pub async fn log_level_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let log_filter = telemetry::log_filter().ok_or_else(|| {
+        AppError::unexpected(anyhow::anyhow!("The log filter has not been initialised"))
+    })?;
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let directives = utils::escape_html(&log_filter.directives());
+    let default_directives = utils::escape_html(log_filter.default_directives());
+    let revert_html = match log_filter.reverts_in() {
+        Some(reverts_in) => format!(
+            "<p>Reverts to <code>{}</code> in {}s.</p>",
+            default_directives,
+            reverts_in.as_secs()
+        ),
+        None => "<p>These are the startup directives.</p>".to_string(),
+    };
+    let foreign_html = match log_filter.foreign_targets() {
+        [] => String::new(),
+        targets => format!(
+            "<p>{} run in another process, restart it with <code>RUST_LOG</code> \
+            to change their logs.</p>",
+            targets
+                .iter()
+                .map(|target| format!("<code>{}</code>", target))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Log level</title>
+            </head>
+            <body>
+                {msg_html}
+                <p>Current directives: <code>{directives}</code></p>
+                {revert_html}
+                {foreign_html}
+                <form action="/admin/log-level" method="post">
+                    <label>Directives
+                        <input
+                            type="text"
+                            placeholder="info,zero2prod::routes=debug"
+                            name="directives"
+                            value="{directives}"
+                        >
+                    </label>
+                    <br>
+                    <label>Revert after (seconds)
+                        <input type="number" name="revert_after_seconds" value="900" min="1">
+                    </label>
+                    <br>
+                    <button type="submit">Change log level</button>
+                </form>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,5 @@
+pub use get::log_level_form;
+pub use post::change_log_level;
+
+pub(crate) mod get;
+pub(crate) mod post;
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::authentication::UserId;
+use crate::error::AppError;
+use crate::{telemetry, utils};
+
+// Longest a change can stay in effect before it is reverted
+const MAX_REVERT_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Deserialize, ToSchema)]
+pub struct FormData {
+    // `EnvFilter` directives, e.g. `info,zero2prod::routes=debug`
+    directives: String,
+    revert_after_seconds: u64,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/log-level",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/log-level, with an error message if the directives are invalid or target a module of another process"),
+        (status = 403, description = "Only owners can change the log level, and the CSRF token must be valid")
+    )
+)]
+#[tracing::instrument(name = "Changing the log level", skip(form), fields(user_id=%*user_id, directives=%form.directives))]
+pub async fn change_log_level(
+    form: web::Form<FormData>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let log_filter = telemetry::log_filter().ok_or_else(|| {
+        AppError::unexpected(anyhow::anyhow!("The log filter has not been initialised"))
+    })?;
+    let revert_after = Duration::from_secs(form.revert_after_seconds);
+    if revert_after.is_zero() || revert_after > MAX_REVERT_AFTER {
+        FlashMessage::error(format!(
+            "Changes must be reverted after 1 to {} seconds",
+            MAX_REVERT_AFTER.as_secs()
+        ))
+        .send();
+        return Ok(utils::see_other("/admin/log-level"));
+    }
+    if let Err(e) = log_filter.set(&form.directives, revert_after) {
+        FlashMessage::error(utils::escape_html(&format!("{:#}", e))).send();
+        return Ok(utils::see_other("/admin/log-level"));
+    }
+
+    tracing::info!("Log filter changed");
+    FlashMessage::info(format!(
+        "The log filter has been changed, it will be reverted in {}s",
+        revert_after.as_secs()
+    ))
+    .send();
+    Ok(utils::see_other("/admin/log-level"))
+}
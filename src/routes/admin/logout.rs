@@ -1,9 +1,31 @@
+use crate::authentication::{SessionId, UserId};
+use crate::error::AppError;
+use crate::session_registry::SessionRegistry;
 use crate::session_state::TypedSession;
 use crate::utils;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 
-pub async fn logout(session: TypedSession) -> Result<HttpResponse, actix_web::Error> {
+#[utoipa::path(
+    post,
+    path = "/admin/logout",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 303, description = "Ends the session and redirects to /login"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn logout(
+    session: TypedSession,
+    registry: web::Data<SessionRegistry>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    registry
+        .revoke(user_id.0, session_id.0)
+        .await
+        .map_err(AppError::unexpected)?;
     session.logout();
     FlashMessage::info("You have successfully logged out").send();
     Ok(utils::see_other("/login"))
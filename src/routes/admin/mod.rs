@@ -1,9 +1,19 @@
+pub use api_tokens::*;
 pub use dashboard::*;
+pub use lockouts::*;
+pub use log_level::*;
 pub use logout::*;
 pub use newsletters::*;
 pub use password::*;
+pub use security::*;
+pub use sessions::*;
 
-mod dashboard;
-mod logout;
-mod newsletters;
-mod password;
+pub(crate) mod api_tokens;
+pub(crate) mod dashboard;
+pub(crate) mod lockouts;
+pub(crate) mod log_level;
+pub(crate) mod logout;
+pub(crate) mod newsletters;
+pub(crate) mod password;
+pub(crate) mod security;
+pub(crate) mod sessions;
@@ -1,5 +1,6 @@
 pub use get::publish_newsletter_form;
-pub use post::publish_newsletter;
+pub(crate) use post::{enqueue_delivery_tasks, insert_newsletter_issue};
+pub use post::{publish_newsletter, send_newsletter_published};
 
-mod get;
-mod post;
+pub(crate) mod get;
+pub(crate) mod post;
@@ -1,72 +1,75 @@
-use crate::authentication::UserId;
-use actix_web::web::ReqData;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
-use sqlx::{Executor, PgPool, Postgres, Transaction};
+use sqlx::{Executor, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::idempotency;
+use crate::error::AppError;
+use crate::idempotency::IdempotentTransaction;
 use crate::utils;
 
 const NEWSLETTER_PUBLISHED: &str = "The newsletter issue has been accepted - \
     emails will go out shortly.";
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
     title: String,
     html_content: String,
     text_content: String,
+    // Read by the idempotency middleware
+    #[allow(dead_code)]
     idempotency_key: String,
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/newsletters",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "The issue has been queued for delivery, redirects to /admin/newsletters"),
+        (status = 400, description = "The idempotency key is missing or invalid"),
+        (status = 409, description = "The same form is still being processed, retry after `Retry-After` seconds"),
+        (status = 422, description = "The idempotency key has already been used for a different issue"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
 #[tracing::instrument(
     name = "Publishing newsletters to confirmed subscribers",
-    skip(form, db_pool),
+    skip(form, transaction),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn publish_newsletter(
     form: web::Form<FormData>,
-    db_pool: web::Data<PgPool>,
-    user_id: ReqData<UserId>,
+    transaction: IdempotentTransaction,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
     let FormData {
         title,
         text_content,
         html_content,
-        idempotency_key,
+        ..
     } = form.0;
-    let idempotency_key: idempotency::IdempotencyKey =
-        idempotency_key.try_into().map_err(utils::error_400)?;
-    let mut transaction = match idempotency::try_processing(&db_pool, &idempotency_key, *user_id)
-        .await
-        .map_err(utils::error_500)?
-    {
-        idempotency::NextAction::StartProcessing(t) => t,
-        idempotency::NextAction::ReturnSavedResponse(saved_response) => {
-            FlashMessage::info(NEWSLETTER_PUBLISHED).send();
-            return Ok(saved_response);
-        }
-    };
+    let mut transaction = transaction.lock().await;
     let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
         .await
         .context("Failed to store newsletter issue details")
-        .map_err(utils::error_500)?;
+        .map_err(AppError::unexpected)?;
     enqueue_delivery_tasks(&mut transaction, issue_id)
         .await
         .context("Failed to enqueue delivery tasks")
-        .map_err(utils::error_500)?;
-    let response = utils::see_other("/admin/newsletters");
-    let response = idempotency::save_response(transaction, &idempotency_key, *user_id, response)
-        .await
-        .map_err(utils::error_500)?;
+        .map_err(AppError::unexpected)?;
+    send_newsletter_published();
+    Ok(utils::see_other("/admin/newsletters"))
+}
+
+// Also sent when the idempotency middleware replays the response of a resubmitted form
+pub fn send_newsletter_published() {
     FlashMessage::info(NEWSLETTER_PUBLISHED).send();
-    Ok(response)
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
@@ -95,7 +98,7 @@ async fn insert_newsletter_issue(
 }
 
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+pub(crate) async fn enqueue_delivery_tasks(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
 ) -> Result<(), sqlx::Error> {
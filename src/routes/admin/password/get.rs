@@ -3,6 +3,16 @@ use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
 
+#[utoipa::path(
+    get,
+    path = "/admin/password",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The password change form", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in")
+    )
+)]
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
@@ -1,5 +1,5 @@
 pub use get::change_password_form;
 pub use post::change_password;
 
-mod get;
-mod post;
+pub(crate) mod get;
+pub(crate) mod post;
@@ -1,25 +1,44 @@
+use crate::error::AppError;
 use crate::utils;
 
 use crate::authentication;
-use crate::authentication::UserId;
+use crate::authentication::{SessionId, UserId};
 use crate::routes::admin;
+use crate::session_registry::SessionRegistry;
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use sqlx::PgPool;
+use utoipa::ToSchema;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct FormData {
+    #[schema(value_type = String, format = Password)]
     old_password: Secret<String>,
+    #[schema(value_type = String, format = Password)]
     new_password: Secret<String>,
+    #[schema(value_type = String, format = Password)]
     new_password_confirm: Secret<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/password",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/password with the outcome as a flash message"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
 pub async fn change_password(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
+    registry: web::Data<SessionRegistry>,
     user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
 ) -> Result<HttpResponse, actix_web::Error> {
     // New passwords must be greater than 12 and shorter than 129
     if form.new_password.expose_secret().len() < 13 || form.new_password.expose_secret().len() > 128
@@ -41,7 +60,7 @@ pub async fn change_password(
     // Entered current password must be correct
     let username = admin::fetch_username(&user_id.0, &db_pool)
         .await
-        .map_err(utils::error_500)?;
+        .map_err(AppError::unexpected)?;
     let credentials = authentication::Credentials {
         username,
         password: form.0.old_password,
@@ -52,12 +71,17 @@ pub async fn change_password(
                 FlashMessage::error("The current password is incorrect").send();
                 Ok(utils::see_other("/admin/password"))
             }
-            authentication::AuthError::UnexpectedError(e) => Err(utils::error_500(e)),
+            authentication::AuthError::UnexpectedError(e) => Err(AppError::unexpected(e).into()),
         };
     }
     authentication::change_password(user_id.0, form.0.new_password, &db_pool)
         .await
-        .map_err(utils::error_500)?;
+        .map_err(AppError::unexpected)?;
+    // Whoever knew the old password must not stay logged in elsewhere
+    registry
+        .revoke_all_except(user_id.0, session_id.0)
+        .await
+        .map_err(AppError::unexpected)?;
 
     FlashMessage::info("You have successfully changed your password").send();
     Ok(utils::see_other("/admin/password"))
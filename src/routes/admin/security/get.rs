@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::authentication;
+use crate::authentication::UserId;
+use crate::error::AppError;
+use crate::routes::admin;
+use crate::session_state::TypedSession;
+
+#[utoipa::path(
+    get,
+    path = "/admin/security",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The two-factor authentication settings", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in")
+    )
+)]
+pub async fn security_form(
+    flash_messages: IncomingFlashMessages,
+    db_pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let totp_enabled = authentication::get_totp_secret(user_id.0, &db_pool)
+        .await
+        .map_err(AppError::unexpected)?
+        .is_some();
+    let enrollment_secret = session
+        .get_totp_enrollment_secret()
+        .map_err(AppError::unexpected)?;
+
+    let totp_html = match (totp_enabled, enrollment_secret) {
+        (true, _) => r#"
+                <p>Two-factor authentication is <b>enabled</b>.</p>
+                <form action="/admin/security/totp/disable" method="post">
+                    <label>Authentication code
+                        <input
+                            type="text"
+                            autocomplete="one-time-code"
+                            placeholder="Code from your app or a recovery code"
+                            name="code"
+                        >
+                    </label>
+                    <button type="submit">Disable two-factor authentication</button>
+                </form>"#
+            .to_string(),
+        (false, Some(secret)) => {
+            let username = admin::fetch_username(&user_id.0, &db_pool)
+                .await
+                .map_err(AppError::unexpected)?;
+            let uri = authentication::totp_provisioning_uri(&secret, &username)
+                .map_err(AppError::unexpected)?;
+            let qr_code = authentication::totp_qr_code_base64(&secret, &username)
+                .map_err(AppError::unexpected)?;
+            let secret = secret.expose_secret();
+            format!(
+                r#"
+                <p>Scan this QR code with your authenticator app:</p>
+                <img alt="TOTP QR code" src="data:image/png;base64,{qr_code}">
+                <p>Or add it manually with the key <code>{secret}</code>
+                (provisioning URI: <code>{uri}</code>).</p>
+                <form action="/admin/security/totp/confirm" method="post">
+                    <label>Authentication code
+                        <input
+                            type="text"
+                            autocomplete="one-time-code"
+                            placeholder="Enter the code shown by your app"
+                            name="code"
+                        >
+                    </label>
+                    <button type="submit">Confirm</button>
+                </form>"#
+            )
+        }
+        (false, None) => r#"
+                <p>Two-factor authentication is <b>disabled</b>.</p>
+                <form action="/admin/security/totp" method="post">
+                    <button type="submit">Enable two-factor authentication</button>
+                </form>"#
+            .to_string(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Security</title>
+            </head>
+            <body>
+                {msg_html}
+                {totp_html}
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
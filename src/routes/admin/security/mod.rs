@@ -0,0 +1,5 @@
+pub use get::security_form;
+pub use post::{confirm_totp_enrollment, disable_totp, start_totp_enrollment};
+
+pub(crate) mod get;
+pub(crate) mod post;
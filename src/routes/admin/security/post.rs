@@ -0,0 +1,150 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::authentication;
+use crate::authentication::UserId;
+use crate::clock::Clock;
+use crate::error::AppError;
+use crate::session_state::TypedSession;
+use crate::utils;
+
+#[derive(Deserialize, ToSchema)]
+pub struct FormData {
+    #[schema(value_type = String, format = Password)]
+    code: Secret<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/security/totp",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 303, description = "Generates a secret to enroll and redirects to /admin/security"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn start_totp_enrollment(
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The secret only lives in the session until the user proves they have set up their app
+    let secret = authentication::generate_totp_secret();
+    session
+        .insert_totp_enrollment_secret(&secret)
+        .map_err(AppError::unexpected)?;
+    Ok(utils::see_other("/admin/security"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/security/totp/confirm",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/security if the code is incorrect"),
+        (status = 200, description = "Two-factor authentication is enabled, shows the recovery codes", content_type = "text/html", body = String),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn confirm_totp_enrollment(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let secret = match session
+        .get_totp_enrollment_secret()
+        .map_err(AppError::unexpected)?
+    {
+        Some(secret) => secret,
+        None => {
+            FlashMessage::error("Start enabling two-factor authentication first").send();
+            return Ok(utils::see_other("/admin/security"));
+        }
+    };
+    if !authentication::verify_totp_code(&secret, form.code.expose_secret(), clock.now())
+        .map_err(AppError::unexpected)?
+    {
+        FlashMessage::error("The authentication code is incorrect").send();
+        return Ok(utils::see_other("/admin/security"));
+    }
+
+    let recovery_codes = authentication::generate_recovery_codes();
+    authentication::enable_totp(user_id.0, &secret, &recovery_codes, &db_pool)
+        .await
+        .map_err(AppError::unexpected)?;
+    session.remove_totp_enrollment_secret();
+
+    // Recovery codes are only stored hashed, so this is the one time we can show them
+    let mut codes_html = String::new();
+    for code in &recovery_codes {
+        writeln!(codes_html, "<li><code>{}</code></li>", code.expose_secret()).unwrap();
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Recovery codes</title>
+            </head>
+            <body>
+                <p>Two-factor authentication has been enabled.</p>
+                <p>Store these recovery codes somewhere safe.
+                Each of them can be used once if you lose access to your app:</p>
+                <ul>
+                    {codes_html}
+                </ul>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/security/totp/disable",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/security with the outcome as a flash message"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn disable_totp(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Err(e) =
+        authentication::validate_second_factor(user_id.0, form.0.code, clock.now(), &db_pool).await
+    {
+        return match e {
+            authentication::AuthError::InvalidCredentials(_) => {
+                FlashMessage::error("The authentication code is incorrect").send();
+                Ok(utils::see_other("/admin/security"))
+            }
+            authentication::AuthError::UnexpectedError(e) => Err(AppError::unexpected(e).into()),
+        };
+    }
+    authentication::disable_totp(user_id.0, &db_pool)
+        .await
+        .map_err(AppError::unexpected)?;
+
+    FlashMessage::info("Two-factor authentication has been disabled").send();
+    Ok(utils::see_other("/admin/security"))
+}
@@ -0,0 +1,95 @@
+use std::fmt::Write;
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::authentication::{SessionId, UserId};
+use crate::error::AppError;
+use crate::session_registry::SessionRegistry;
+use crate::utils;
+
+#[utoipa::path(
+    get,
+    path = "/admin/sessions",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    responses(
+        (status = 200, description = "The active sessions of the user", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the user is not logged in")
+    )
+)]
+pub async fn sessions(
+    flash_messages: IncomingFlashMessages,
+    registry: web::Data<SessionRegistry>,
+    user_id: web::ReqData<UserId>,
+    session_id: web::ReqData<SessionId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let sessions = registry
+        .list(user_id.0)
+        .await
+        .map_err(AppError::unexpected)?;
+    let mut sessions_html = String::new();
+    for session in &sessions {
+        let device = utils::escape_html(session.user_agent.as_deref().unwrap_or("Unknown device"));
+        let ip = utils::escape_html(session.ip.as_deref().unwrap_or("Unknown"));
+        let last_seen = session.last_seen.format("%Y-%m-%d %H:%M:%S UTC");
+        // The current session is ended by logging out
+        let action = if session.session_id == session_id.0 {
+            "This session".to_string()
+        } else {
+            format!(
+                r#"<form action="/admin/sessions/revoke" method="post">
+                                <input hidden type="text" name="session_id" value="{}">
+                                <button type="submit">Revoke</button>
+                            </form>"#,
+                session.session_id
+            )
+        };
+        writeln!(
+            sessions_html,
+            r#"
+                    <tr>
+                        <td>{device}</td>
+                        <td>{ip}</td>
+                        <td>{last_seen}</td>
+                        <td>
+                            {action}
+                        </td>
+                    </tr>"#,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+        <!DOCTYPE html>
+        <html lang="en">
+            <head>
+                <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                <title>Active sessions</title>
+            </head>
+            <body>
+                {msg_html}
+                <table>
+                    <tr>
+                        <th>Device</th>
+                        <th>IP address</th>
+                        <th>Last seen</th>
+                        <th></th>
+                    </tr>
+                    {sessions_html}
+                </table>
+                <p><a href="/admin/dashboard">&lt;- Back</a></p>
+            </body>
+        </html>
+        "#,
+        )))
+}
@@ -0,0 +1,5 @@
+pub use get::sessions;
+pub use post::revoke_session;
+
+pub(crate) mod get;
+pub(crate) mod post;
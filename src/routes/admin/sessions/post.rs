@@ -0,0 +1,45 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::error::AppError;
+use crate::session_registry::SessionRegistry;
+use crate::utils;
+
+#[derive(Deserialize, ToSchema)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/sessions/revoke",
+    tag = "admin",
+    security(("session_cookie" = [])),
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to /admin/sessions with the outcome as a flash message"),
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+pub async fn revoke_session(
+    form: web::Form<FormData>,
+    registry: web::Data<SessionRegistry>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Users can only revoke their own sessions
+    let revoked = registry
+        .revoke(user_id.0, form.0.session_id)
+        .await
+        .map_err(AppError::unexpected)?;
+
+    if revoked {
+        FlashMessage::info("The session has been revoked").send();
+    } else {
+        FlashMessage::error("The session does not exist or has already ended").send();
+    }
+    Ok(utils::see_other("/admin/sessions"))
+}
@@ -0,0 +1,186 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::error::AppError;
+use crate::idempotency::IdempotentTransaction;
+use crate::routes::admin::{enqueue_delivery_tasks, insert_newsletter_issue};
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewIssue {
+    title: String,
+    html_content: String,
+    text_content: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PublishedIssue {
+    issue_id: Uuid,
+    status_url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueSummary {
+    issue_id: Uuid,
+    title: String,
+    published_at: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueList {
+    issues: Vec<IssueSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueStatus {
+    issue_id: Uuid,
+    title: String,
+    published_at: String,
+    // Deliveries are removed from the queue once they have been attempted
+    pending_deliveries: i64,
+    // Attempted deliveries that could not be sent, they are not retried
+    failed_deliveries: i64,
+    // `in_progress`, `delivered`, or `completed_with_failures` if some deliveries failed
+    status: &'static str,
+}
+
+// Retrying with the same `Idempotency-Key` header returns the original response
+// instead of publishing the issue again.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "api",
+    security(("bearer_token" = [])),
+    params(("Idempotency-Key" = String, Header, description = "Retrying with the same key returns the original response")),
+    request_body = NewIssue,
+    responses(
+        (status = 202, description = "The issue has been queued for delivery", body = PublishedIssue),
+        (status = 400, description = "Invalid issue or missing idempotency key", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 409, description = "A request with the same idempotency key is still being processed, retry after `Retry-After` seconds", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "The idempotency key has already been used for a different issue", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(
+    name = "Publishing newsletter issue through the API",
+    skip(body, transaction),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_issue(
+    body: web::Json<NewIssue>,
+    transaction: IdempotentTransaction,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, AppError> {
+    let NewIssue {
+        title,
+        html_content,
+        text_content,
+    } = body.into_inner();
+    if title.trim().is_empty() || (html_content.is_empty() && text_content.is_empty()) {
+        return Err(AppError::BadRequest(
+            "An issue needs a title and some content".to_string(),
+        ));
+    }
+
+    let mut transaction = transaction.lock().await;
+    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut transaction, issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    Ok(HttpResponse::Accepted().json(PublishedIssue {
+        issue_id,
+        status_url: format!("/api/v1/issues/{}", issue_id),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues",
+    tag = "api",
+    security(("bearer_token" = [])),
+    responses(
+        (status = 200, description = "Published issues, most recent first", body = IssueList),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing newsletter issues through the API", skip(db_pool))]
+pub async fn list_issues(db_pool: web::Data<PgPool>) -> Result<HttpResponse, AppError> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id AS issue_id, title, published_at
+        FROM newsletter_issue
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issues")?;
+    Ok(HttpResponse::Ok().json(IssueList { issues }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{issue_id}",
+    tag = "api",
+    security(("bearer_token" = [])),
+    params(("issue_id" = Uuid, Path, description = "Id of the newsletter issue")),
+    responses(
+        (status = 200, description = "Delivery status of the issue", body = IssueStatus),
+        (status = 404, description = "There is no issue with this id", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Getting newsletter issue delivery status", skip(db_pool))]
+pub async fn get_issue(
+    issue_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at,
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "pending_deliveries!",
+            (
+                SELECT COUNT(*)
+                FROM issue_delivery_failure f
+                WHERE f.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "failed_deliveries!"
+        FROM newsletter_issue i
+        WHERE newsletter_issue_id = $1
+        "#,
+        *issue_id
+    )
+    .fetch_optional(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve newsletter issue")?
+    .ok_or_else(|| AppError::NotFound("There is no newsletter issue with this id".to_string()))?;
+
+    let status = if row.pending_deliveries > 0 {
+        "in_progress"
+    } else if row.failed_deliveries > 0 {
+        "completed_with_failures"
+    } else {
+        "delivered"
+    };
+    Ok(HttpResponse::Ok().json(IssueStatus {
+        issue_id: row.newsletter_issue_id,
+        title: row.title,
+        published_at: row.published_at,
+        pending_deliveries: row.pending_deliveries,
+        failed_deliveries: row.failed_deliveries,
+        status,
+    }))
+}
@@ -0,0 +1,5 @@
+pub use issues::*;
+pub use subscribers::*;
+
+pub(crate) mod issues;
+pub(crate) mod subscribers;
@@ -0,0 +1,188 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::domain::{EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::error::AppError;
+use crate::routes::register_subscriber;
+use crate::startup::ApplicationBaseUrl;
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewSubscriberBody {
+    email: String,
+    name: String,
+}
+
+#[derive(Deserialize, Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParams {
+    /// Only list subscribers with this status: `confirmed` or `pending_confirmation`
+    status: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberList {
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "api",
+    security(("bearer_token" = [])),
+    params(ListParams),
+    responses(
+        (status = 200, description = "Subscribers, oldest first", body = SubscriberList),
+        (status = 400, description = "Unknown status", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Listing subscribers through the API", skip(db_pool))]
+pub async fn list_subscribers(
+    params: web::Query<ListParams>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    if let Some(status) = &params.status {
+        if status != "confirmed" && status != "pending_confirmation" {
+            return Err(AppError::BadRequest(format!(
+                "{} is not a valid status. Use either 'confirmed' or 'pending_confirmation'",
+                status
+            )));
+        }
+    }
+    let subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscription
+        WHERE $1::TEXT IS NULL OR status = $1
+        ORDER BY subscribed_at
+        "#,
+        params.status
+    )
+    .fetch_all(db_pool.get_ref())
+    .await
+    .context("Failed to retrieve subscribers")?;
+    Ok(HttpResponse::Ok().json(SubscriberList { subscribers }))
+}
+
+// Subscribers added through the API still have to confirm their email address.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "api",
+    security(("bearer_token" = [])),
+    request_body = NewSubscriberBody,
+    responses(
+        (status = 201, description = "The subscriber has been stored and a confirmation email sent", body = Subscriber),
+        (status = 200, description = "A subscriber already had this email, a pending one is sent a new confirmation email", body = Subscriber),
+        (status = 400, description = "Invalid or refused name or email address", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(body, db_pool, email_client, base_url, email_policy),
+    fields(subscriber_email = %body.email)
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriberBody>,
+    db_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    email_policy: web::Data<EmailPolicy>,
+) -> Result<HttpResponse, AppError> {
+    let NewSubscriberBody { email, name } = body.into_inner();
+    let email = SubscriberEmail::parse(email).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let new_subscriber = NewSubscriber {
+        email: email_policy
+            .check(email)
+            .await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?,
+        name: SubscriberName::parse(name).map_err(AppError::BadRequest)?,
+    };
+    let email = new_subscriber.email.as_ref().to_string();
+
+    let subscriber =
+        register_subscriber(new_subscriber, &db_pool, &email_client, &base_url.0).await?;
+
+    let mut response = if subscriber.is_new {
+        HttpResponse::Created()
+    } else {
+        HttpResponse::Ok()
+    };
+    Ok(response.json(Subscriber {
+        id: subscriber.id,
+        email,
+        name: subscriber.name,
+        status: subscriber.status,
+        subscribed_at: subscriber.subscribed_at,
+    }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "api",
+    security(("bearer_token" = [])),
+    params(("subscriber_id" = Uuid, Path, description = "Id of the subscriber")),
+    responses(
+        (status = 204, description = "The subscriber and their pending deliveries have been deleted"),
+        (status = 404, description = "There is no subscriber with this id", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing, unknown or revoked API token", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+#[tracing::instrument(name = "Deleting a subscriber through the API", skip(db_pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    db_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = db_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    sqlx::query!(
+        "DELETE FROM subscription_token WHERE subscription_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscription tokens")?;
+    let deleted = sqlx::query!(
+        "DELETE FROM subscription WHERE id = $1 RETURNING email",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete subscriber")?
+    .ok_or_else(|| AppError::NotFound("There is no subscriber with this id".to_string()))?;
+    // Issues that are still being delivered must not reach them anymore
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        deleted.email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to cancel pending deliveries")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber")?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,5 +1,186 @@
-use actix_web::HttpResponse;
+use std::future::Future;
+use std::time::Duration;
 
+use actix_web::{web, HttpResponse};
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::issue_delivery_worker::WORKER_NAME;
+use crate::startup::pending_migrations;
+
+// Each check gives up after this long, so that a hanging dependency fails the probe
+// instead of stalling it
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+// The worker records a heartbeat every `HEARTBEAT_INTERVAL` while it runs
+const WORKER_HEARTBEAT_MAX_AGE: Duration = Duration::from_secs(60);
+
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "operations",
+    responses((status = 200, description = "The application is up"))
+)]
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+// Whether readiness depends on the delivery worker. Only when it runs in the same process:
+// a worker scaled on its own going down must not take the API out of rotation.
+#[derive(Clone, Copy)]
+pub struct WorkerInProcess(pub bool);
+
+#[derive(Serialize, ToSchema)]
+pub struct Readiness {
+    #[schema(example = "up")]
+    status: &'static str,
+    database: ComponentStatus,
+    redis: ComponentStatus,
+    migrations: ComponentStatus,
+    // Only checked if the worker runs in this process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    worker: Option<ComponentStatus>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ComponentStatus {
+    #[schema(example = "down")]
+    status: &'static str,
+    // Why the component is down. Errors of the drivers are only logged, they may name
+    // hosts and databases.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<Result<(), String>> for ComponentStatus {
+    fn from(outcome: Result<(), String>) -> Self {
+        match outcome {
+            Ok(()) => Self {
+                status: "up",
+                error: None,
+            },
+            Err(error) => Self {
+                status: "down",
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Every dependency is available", body = Readiness),
+        (status = 503, description = "At least one dependency is not available", body = Readiness)
+    )
+)]
+pub async fn readiness(
+    db_pool: web::Data<PgPool>,
+    redis: web::Data<ConnectionManager>,
+    worker_in_process: web::Data<WorkerInProcess>,
+) -> HttpResponse {
+    let check_worker = async {
+        if worker_in_process.0 {
+            Some(check(check_worker(&db_pool)).await)
+        } else {
+            None
+        }
+    };
+    let (database, redis, migrations, worker) = tokio::join!(
+        check(check_database(&db_pool)),
+        check(check_redis(redis.get_ref().clone())),
+        check(check_migrations(&db_pool)),
+        check_worker,
+    );
+    let ready = [&database, &redis, &migrations]
+        .into_iter()
+        .chain(worker.as_ref())
+        .all(|c| c.status == "up");
+    let readiness = Readiness {
+        status: if ready { "up" } else { "down" },
+        database,
+        redis,
+        migrations,
+        worker,
+    };
+    if ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+async fn check(outcome: impl Future<Output = Result<(), String>>) -> ComponentStatus {
+    tokio::time::timeout(CHECK_TIMEOUT, outcome)
+        .await
+        .unwrap_or_else(|_| Err("Timed out".to_string()))
+        .into()
+}
+
+fn unavailable(component: &str, e: impl std::error::Error) -> String {
+    tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "The readiness check of {} failed",
+        component
+    );
+    "unavailable".to_string()
+}
+
+async fn check_database(db_pool: &PgPool) -> Result<(), String> {
+    sqlx::query!("SELECT 1 AS one")
+        .fetch_one(db_pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| unavailable("database", e))
+}
+
+// Sessions live in Redis: without it nobody can log in
+async fn check_redis(mut redis: ConnectionManager) -> Result<(), String> {
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut redis)
+        .await
+        .map(|_| ())
+        .map_err(|e| unavailable("redis", e))
+}
+
+// Every migration this binary was built with must have been applied successfully
+async fn check_migrations(db_pool: &PgPool) -> Result<(), String> {
+    let pending: Vec<String> = pending_migrations(db_pool)
+        .await
+        .map_err(|e| unavailable("migrations", e))?
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Pending migrations: {}", pending.join(", ")))
+    }
+}
+
+async fn check_worker(db_pool: &PgPool) -> Result<(), String> {
+    let age = sqlx::query_scalar!(
+        r#"
+        SELECT EXTRACT(EPOCH FROM now() - last_seen_at)::float8 AS "age!"
+        FROM worker_heartbeat
+        WHERE worker = $1
+        "#,
+        WORKER_NAME
+    )
+    .fetch_optional(db_pool)
+    .await
+    .map_err(|e| unavailable("worker", e))?
+    .ok_or_else(|| "The delivery worker has never reported".to_string())?;
+    if age > WORKER_HEARTBEAT_MAX_AGE.as_secs_f64() {
+        Err(format!(
+            "No heartbeat from the delivery worker for {:.0}s",
+            age
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -1,6 +1,12 @@
 use actix_web::http::header::ContentType;
 use actix_web::HttpResponse;
 
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "pages",
+    responses((status = 200, description = "The home page", content_type = "text/html", body = String))
+)]
 pub async fn home() -> HttpResponse {
     HttpResponse::Ok()
         .content_type(ContentType::html())
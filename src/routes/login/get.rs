@@ -3,6 +3,12 @@ use std::fmt::Write;
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 
+#[utoipa::path(
+    get,
+    path = "/login",
+    tag = "login",
+    responses((status = 200, description = "The login form", content_type = "text/html", body = String))
+)]
 pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
     let mut error_html = String::new();
     for message in flash_messages.iter() {
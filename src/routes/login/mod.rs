@@ -1,5 +1,7 @@
 pub use get::login_form;
 pub use post::login;
+pub use two_factor::*;
 
-mod get;
-mod post;
+pub(crate) mod get;
+pub(crate) mod post;
+pub(crate) mod two_factor;
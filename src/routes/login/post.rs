@@ -1,55 +1,140 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use actix_web::error::InternalError;
-use actix_web::{web, HttpResponse};
+use actix_web::http::header::USER_AGENT;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use serde::Deserialize;
 use sqlx::PgPool;
+use utoipa::openapi::header::Header;
+use utoipa::openapi::{
+    ObjectBuilder, RefOr, Response, ResponseBuilder, ResponsesBuilder, SchemaType,
+};
+use utoipa::ToSchema;
+use uuid::Uuid;
 
+use crate::client_ip::client_ip;
+use crate::clock::Clock;
+use crate::error::AppError;
+use crate::session_registry::SessionRegistry;
 use crate::session_state::TypedSession;
 use crate::{authentication, utils};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct FormData {
     username: String,
+    #[schema(value_type = String, format = Password)]
     password: Secret<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "login",
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to the dashboard, or to the second factor form if enabled"),
+        LoginError,
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
 #[tracing::instrument(
-    skip(form, db_pool, session),
+    skip(form, db_pool, clock, throttle, registry, session, request),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    throttle: web::Data<authentication::LoginThrottle>,
+    registry: web::Data<SessionRegistry>,
     session: TypedSession,
-) -> Result<HttpResponse, InternalError<LoginError>> {
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = form.0.username;
+    tracing::Span::current().record("username", tracing::field::display(&username));
+    let client_ip = client_ip(&request);
+
+    // Refuse locked out attempts before paying for a password hash verification
+    if let Some(retry_after) = throttle
+        .attempt(&username, client_ip)
+        .await
+        .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e)))?
+    {
+        return Err(lockout_response(retry_after));
+    }
+
     let credentials = authentication::Credentials {
-        username: form.0.username,
+        username: username.clone(),
         password: form.0.password,
     };
 
     match authentication::validate_credentials(credentials, &db_pool).await {
         Ok(user_id) => {
-            tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+            throttle
+                .forgive(&username, client_ip)
+                .await
+                .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e)))?;
+            let totp_secret = authentication::get_totp_secret(user_id, &db_pool)
+                .await
+                .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e)))?;
             session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e.into())))?;
+            if totp_secret.is_some() {
+                // The password is correct, but the user is only logged in once
+                // they have also provided their second factor
+                session
+                    .insert_pending_second_factor(user_id)
+                    .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(utils::see_other("/login/two-factor"));
+            }
+            // Only a full authentication clears the failures: resetting on a correct
+            // password alone would let it be resubmitted between second factor guesses
+            throttle
+                .reset(&username)
+                .await
+                .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(&session, &registry, user_id, &request, clock.now())
+                .await
+                .map_err(|e| login_failure_redirect(LoginError::UnexpectedError(e)))?;
 
             Ok(utils::see_other("/admin/dashboard"))
         }
         Err(e) => {
             let e = match e {
+                // The attempt has already been counted
                 authentication::AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
                 authentication::AuthError::UnexpectedError(_) => {
                     LoginError::UnexpectedError(e.into())
                 }
             };
-            Err(login_failure_redirect(e))
+            Err(login_failure_redirect(e).into())
         }
     }
 }
 
+// Log the user in and register the session so that it can be listed and revoked
+pub(super) async fn start_session(
+    session: &TypedSession,
+    registry: &SessionRegistry,
+    user_id: Uuid,
+    request: &HttpRequest,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(), anyhow::Error> {
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    let ip = client_ip(request).map(|ip| ip.to_string());
+    let session_id = registry.register(user_id, user_agent, ip, now).await?;
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    Ok(())
+}
+
 fn login_failure_redirect(e: LoginError) -> InternalError<LoginError> {
     // Set the "_flash" error cookie message
     FlashMessage::error(e.to_string()).send();
@@ -57,6 +142,18 @@ fn login_failure_redirect(e: LoginError) -> InternalError<LoginError> {
     InternalError::from_response(e, response)
 }
 
+// Lockouts are not redirected: the client gets a 429 telling it when to try again
+pub(super) fn lockout_response(retry_after: Duration) -> actix_web::Error {
+    AppError::TooManyRequests {
+        detail: format!(
+            "Too many failed login attempts. Try again in {} seconds.",
+            retry_after.as_secs()
+        ),
+        retry_after,
+    }
+    .into()
+}
+
 #[derive(thiserror::Error)]
 pub enum LoginError {
     #[error("Authentication failed")]
@@ -70,3 +167,27 @@ impl std::fmt::Debug for LoginError {
         utils::error_chain_fmt(self, f)
     }
 }
+
+// Failures are reported through a flash message on the page we redirect to,
+// except lockouts which are a 429.
+impl utoipa::IntoResponses for LoginError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        ResponsesBuilder::new()
+            .response(
+                "303",
+                ResponseBuilder::new()
+                    .description("Authentication failed, redirects back to the form"),
+            )
+            .response(
+                "429",
+                ResponseBuilder::new()
+                    .description("Too many failed login attempts")
+                    .header(
+                        "Retry-After",
+                        Header::new(ObjectBuilder::new().schema_type(SchemaType::Integer)),
+                    ),
+            )
+            .build()
+            .into()
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt::Write;
+
+use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+
+use crate::error::AppError;
+use crate::session_state::TypedSession;
+use crate::utils;
+
+#[utoipa::path(
+    get,
+    path = "/login/two-factor",
+    tag = "login",
+    responses(
+        (status = 200, description = "The second factor form", content_type = "text/html", body = String),
+        (status = 303, description = "Redirects to /login if the password has not been verified yet")
+    )
+)]
+pub async fn login_two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Only users who have just entered a valid password can see this page
+    if session
+        .get_pending_second_factor()
+        .map_err(AppError::unexpected)?
+        .is_none()
+    {
+        return Ok(utils::see_other("/login"));
+    }
+
+    let mut error_html = String::new();
+    for message in flash_messages.iter() {
+        writeln!(error_html, "<p><i>{}</i></p>", message.content())
+            .expect("Could not write error message");
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+            <!DOCTYPE html>
+            <html lang="en">
+                <head>
+                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+                    <title>Two-factor authentication</title>
+                </head>
+                <body>
+                    {error_html}
+                    <form action="/login/two-factor" method="post">
+                        <label>Authentication code
+                        <input
+                            type="text"
+                            autocomplete="one-time-code"
+                            placeholder="Code from your app or a recovery code"
+                            name="code"
+                        >
+                        </label>
+                        <button type="submit">Verify</button>
+                    </form>
+                </body>
+            </html>"#,
+        )))
+}
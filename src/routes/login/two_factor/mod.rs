@@ -0,0 +1,5 @@
+pub use get::login_two_factor_form;
+pub use post::login_two_factor;
+
+pub(crate) mod get;
+pub(crate) mod post;
@@ -0,0 +1,105 @@
+use actix_web::error::InternalError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use secrecy::Secret;
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::client_ip::client_ip;
+use crate::clock::Clock;
+use crate::routes::admin;
+use crate::routes::login::post::{lockout_response, start_session, LoginError};
+use crate::session_registry::SessionRegistry;
+use crate::session_state::TypedSession;
+use crate::{authentication, utils};
+
+#[derive(Deserialize, ToSchema)]
+pub struct FormData {
+    #[schema(value_type = String, format = Password)]
+    code: Secret<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/login/two-factor",
+    tag = "login",
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirects to the dashboard once the code has been verified"),
+        LoginError,
+        (status = 403, description = "Missing or invalid CSRF token")
+    )
+)]
+#[tracing::instrument(
+    skip(form, db_pool, clock, throttle, registry, session, request),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_two_factor(
+    form: web::Form<FormData>,
+    db_pool: web::Data<PgPool>,
+    clock: web::Data<Clock>,
+    throttle: web::Data<authentication::LoginThrottle>,
+    registry: web::Data<SessionRegistry>,
+    session: TypedSession,
+    request: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = match session
+        .get_pending_second_factor()
+        .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e.into())))?
+    {
+        Some(user_id) => user_id,
+        None => return Ok(utils::see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    // Codes are short, so guesses count towards the same lockout as wrong passwords
+    let username = admin::fetch_username(&user_id, &db_pool)
+        .await
+        .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e)))?;
+    let client_ip = client_ip(&request);
+    if let Some(retry_after) = throttle
+        .attempt(&username, client_ip)
+        .await
+        .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e)))?
+    {
+        return Err(lockout_response(retry_after));
+    }
+
+    match authentication::validate_second_factor(user_id, form.0.code, clock.now(), &db_pool).await
+    {
+        Ok(()) => {
+            throttle
+                .forgive(&username, client_ip)
+                .await
+                .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e)))?;
+            throttle
+                .reset(&username)
+                .await
+                .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e)))?;
+            session.renew();
+            session.remove_pending_second_factor();
+            start_session(&session, &registry, user_id, &request, clock.now())
+                .await
+                .map_err(|e| two_factor_failure_redirect(LoginError::UnexpectedError(e)))?;
+
+            Ok(utils::see_other("/admin/dashboard"))
+        }
+        Err(e) => {
+            let e = match e {
+                // The attempt has already been counted
+                authentication::AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                authentication::AuthError::UnexpectedError(_) => {
+                    LoginError::UnexpectedError(e.into())
+                }
+            };
+            Err(two_factor_failure_redirect(e).into())
+        }
+    }
+}
+
+fn two_factor_failure_redirect(e: LoginError) -> InternalError<LoginError> {
+    FlashMessage::error(e.to_string()).send();
+    let response = utils::see_other("/login/two-factor");
+    InternalError::from_response(e, response)
+}
@@ -1,13 +1,19 @@
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
 pub use login::*;
+pub use openapi::*;
+pub use prometheus::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
 
 mod admin;
+mod api;
 mod health_check;
 mod home;
 mod login;
+mod openapi;
+mod prometheus;
 mod subscriptions;
 mod subscriptions_confirm;
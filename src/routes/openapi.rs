@@ -0,0 +1,98 @@
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::error::ProblemDetails;
+use crate::routes::{
+    admin, api, health_check, home, login, prometheus, subscriptions, subscriptions_confirm,
+};
+
+// Every route registered in `startup` must be listed here, a test checks that they match.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "zero2prod",
+        description = "Newsletter delivery service: public subscription endpoints, \
+            the admin area used from a browser and the JSON API used with API tokens."
+    ),
+    paths(
+        home::home,
+        health_check::health_check,
+        health_check::readiness,
+        prometheus::prometheus_metrics,
+        subscriptions::subscribe,
+        subscriptions_confirm::confirm,
+        login::get::login_form,
+        login::post::login,
+        login::two_factor::get::login_two_factor_form,
+        login::two_factor::post::login_two_factor,
+        admin::dashboard::admin_dashboard,
+        admin::logout::logout,
+        admin::newsletters::get::publish_newsletter_form,
+        admin::newsletters::post::publish_newsletter,
+        admin::password::get::change_password_form,
+        admin::password::post::change_password,
+        admin::security::get::security_form,
+        admin::security::post::start_totp_enrollment,
+        admin::security::post::confirm_totp_enrollment,
+        admin::security::post::disable_totp,
+        admin::sessions::get::sessions,
+        admin::sessions::post::revoke_session,
+        admin::api_tokens::get::api_tokens,
+        admin::api_tokens::post::create_api_token,
+        admin::api_tokens::post::revoke_api_token,
+        admin::lockouts::get::lockouts,
+        admin::lockouts::post::unlock,
+        admin::log_level::get::log_level_form,
+        admin::log_level::post::change_log_level,
+        api::issues::publish_issue,
+        api::issues::list_issues,
+        api::issues::get_issue,
+        api::subscribers::list_subscribers,
+        api::subscribers::create_subscriber,
+        api::subscribers::delete_subscriber,
+        openapi_spec,
+    ),
+    components(schemas(
+        health_check::Readiness,
+        health_check::ComponentStatus,
+        ProblemDetails,
+        api::issues::NewIssue,
+        api::issues::PublishedIssue,
+        api::issues::IssueSummary,
+        api::issues::IssueList,
+        api::issues::IssueStatus,
+        api::subscribers::NewSubscriberBody,
+        api::subscribers::Subscriber,
+        api::subscribers::SubscriberList,
+    )),
+    modifiers(&SecuritySchemes)
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        // Set by actix-session when logging in through /login
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new("id"))),
+        );
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "operations",
+    responses((status = 200, description = "This OpenAPI document", content_type = "application/json"))
+)]
+pub async fn openapi_spec() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,41 @@
+use actix_web::{web, HttpResponse};
+use metrics::gauge;
+use metrics_exporter_prometheus::PrometheusHandle;
+use sqlx::PgPool;
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", content_type = "text/plain", body = String)
+    )
+)]
+pub async fn prometheus_metrics(
+    handle: web::Data<PrometheusHandle>,
+    db_pool: web::Data<PgPool>,
+) -> HttpResponse {
+    // Gauges are sampled when scraped rather than kept up to date
+    match sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(db_pool.get_ref())
+        .await
+    {
+        Ok(queue_depth) => gauge!("issue_delivery_queue_depth").set(queue_depth as f64),
+        // Still serve the other metrics, they may tell why the database is unreachable
+        Err(e) => tracing::warn!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to measure the depth of the delivery queue"
+        ),
+    }
+    let idle = db_pool.num_idle();
+    gauge!("db_pool_connections", "state" => "idle").set(idle as f64);
+    gauge!("db_pool_connections", "state" => "in_use")
+        .set(db_pool.size().saturating_sub(idle as u32) as f64);
+    gauge!("db_pool_max_connections").set(db_pool.options().get_max_connections() as f64);
+
+    handle.run_upkeep();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(handle.render())
+}
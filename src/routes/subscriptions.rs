@@ -2,67 +2,177 @@ use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::ops::DerefMut;
 
-use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse};
 use anyhow::Context;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rand::distributions::Alphanumeric;
 use rand::Rng;
 use serde::Deserialize;
 use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::client_ip::client_ip;
+use crate::domain::{EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName};
 use crate::email_client::EmailClient;
+use crate::error::AppError;
 use crate::startup::ApplicationBaseUrl;
+use crate::subscription_protection::{ChallengeVerifier, SubscriptionThrottle};
 use crate::utils;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct FormData {
     email: String,
     name: String,
+    /// Hidden from humans by the form, bots filling it in are ignored
+    #[serde(default)]
+    website: String,
+    /// Token produced by the challenge widget, required if a verifier is configured
+    #[serde(default)]
+    challenge_response: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
     type Error = String;
 
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let email = SubscriberEmail::parse(value.email)?;
+        let email = SubscriberEmail::parse(value.email).map_err(|e| e.to_string())?;
         let name = SubscriberName::parse(value.name)?;
         Ok(NewSubscriber { email, name })
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content = inline(FormData), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The subscriber has been stored and a confirmation email sent"),
+        (status = 400, description = "The name or the email address is invalid or refused, or the challenge failed", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Too many subscriptions from this IP address or email domain", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Unexpected error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
 #[tracing::instrument(
     "Adding a new subscriber",
-    skip(form, db_pool, email_client, base_url),
+    skip(
+        form,
+        db_pool,
+        email_client,
+        base_url,
+        email_policy,
+        throttle,
+        challenge_verifier,
+        request
+    ),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
     )
 )]
+// Every extractor is a parameter
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
     form: web::Form<FormData>,
     db_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber: NewSubscriber =
-        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    email_policy: web::Data<EmailPolicy>,
+    throttle: web::Data<SubscriptionThrottle>,
+    challenge_verifier: Option<web::Data<ChallengeVerifier>>,
+    request: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let form = form.into_inner();
+    let client_ip = client_ip(&request);
+
+    // Bots are not told they have been spotted
+    if !form.website.is_empty() {
+        tracing::warn!(
+            ?client_ip,
+            "Ignoring a subscription with a filled in honeypot"
+        );
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let challenge_response = form.challenge_response.clone();
+    let mut new_subscriber: NewSubscriber = form.try_into().map_err(AppError::BadRequest)?;
+    new_subscriber.email = email_policy
+        .check(new_subscriber.email)
+        .await
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    if let Some(retry_after) = throttle.attempt(&new_subscriber.email, client_ip).await? {
+        tracing::warn!(?client_ip, "Throttling subscriptions");
+        return Err(AppError::TooManyRequests {
+            detail: format!(
+                "Too many subscriptions. Try again in {} seconds.",
+                retry_after.as_secs()
+            ),
+            retry_after,
+        });
+    }
+
+    if let Some(challenge_verifier) = challenge_verifier {
+        let solved = match challenge_response.as_deref() {
+            Some(response) if !response.is_empty() => {
+                challenge_verifier.verify(response, client_ip).await?
+            }
+            _ => false,
+        };
+        if !solved {
+            return Err(AppError::BadRequest(
+                "The challenge was not solved.".to_string(),
+            ));
+        }
+    }
+
+    register_subscriber(new_subscriber, &db_pool, &email_client, &base_url.0).await?;
 
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) struct RegisteredSubscriber {
+    pub id: Uuid,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+    // False if the email already belonged to a subscriber
+    pub is_new: bool,
+}
+
+// Store a pending subscriber and send them a confirmation email.
+// Subscribing again with the email of a pending subscriber sends them a new confirmation
+// email, while confirmed subscribers are left as they are. Either way the stored subscriber
+// is returned.
+pub(crate) async fn register_subscriber(
+    new_subscriber: NewSubscriber,
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+) -> Result<RegisteredSubscriber, anyhow::Error> {
     // .context() converts our error into an anyhow::Error
     let mut transaction = db_pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+    let subscriber = match insert_subscriber(&new_subscriber, &mut transaction)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to insert new subscriber in the database.")?
+    {
+        Some(subscriber) => subscriber,
+        None => get_subscriber_by_email(&new_subscriber.email, &mut transaction)
+            .await
+            .context("Failed to fetch the subscriber already registered with this email.")?,
+    };
+    if subscriber.status == "confirmed" {
+        return Ok(subscriber);
+    }
 
     let subscription_token = generate_subscription_token();
 
-    store_token(&mut transaction, subscriber_id, &subscription_token)
+    store_token(&mut transaction, subscriber.id, &subscription_token)
         .await
         .context("Failed to store the confirmation token for a new subscriber.")?;
 
@@ -72,37 +182,65 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
     // Send confirmation email to the new subscriber
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscription_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
+    send_confirmation_email(email_client, new_subscriber, base_url, &subscription_token)
+        .await
+        .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(subscriber)
 }
 
+// Nothing is inserted if a subscriber already has this email
 #[tracing::instrument("Saving new subscriber details in the database", skip_all)]
 async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
     transaction: &mut Transaction<'_, Postgres>,
-) -> Result<Uuid, sqlx::Error> {
+) -> Result<Option<RegisteredSubscriber>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    let subscribed_at = sqlx::query_scalar!(
         r#"
             INSERT INTO subscription(id, email, name, subscribed_at, status)
             VALUES ($1, $2, $3, $4, 'pending_confirmation')
+            ON CONFLICT (lower(trim(email))) DO NOTHING
+            RETURNING subscribed_at
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
-    .execute(transaction.deref_mut())
+    .fetch_optional(transaction.deref_mut())
+    .await?;
+    Ok(subscribed_at.map(|subscribed_at| RegisteredSubscriber {
+        id: subscriber_id,
+        name: new_subscriber.name.as_ref().to_string(),
+        status: "pending_confirmation".to_string(),
+        subscribed_at,
+        is_new: true,
+    }))
+}
+
+#[tracing::instrument("Fetching the subscriber registered with an email", skip_all)]
+async fn get_subscriber_by_email(
+    email: &SubscriberEmail,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<RegisteredSubscriber, sqlx::Error> {
+    let subscriber = sqlx::query!(
+        r#"
+            SELECT id, name, status, subscribed_at
+            FROM subscription
+            WHERE lower(trim(email)) = lower(trim($1))
+        "#,
+        email.as_ref()
+    )
+    .fetch_one(transaction.deref_mut())
     .await?;
-    Ok(subscriber_id)
+    Ok(RegisteredSubscriber {
+        id: subscriber.id,
+        name: subscriber.name,
+        status: subscriber.status,
+        subscribed_at: subscriber.subscribed_at,
+        is_new: false,
+    })
 }
 
 #[tracing::instrument(
@@ -161,32 +299,6 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
-#[derive(thiserror::Error)]
-pub enum SubscribeError {
-    // 'error' defines the Display representation. '0' refers to the wrapped String value
-    #[error("{0}")]
-    ValidationError(String), // String does not implement Error so cannot use 'from' or 'source'
-    // Transparent delegates both `Display`'s and `source`'s implementation to the type wrapped
-    // by `UnexpectedError`.
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl Debug for SubscribeError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        utils::error_chain_fmt(self, f)
-    }
-}
-
-impl ResponseError for SubscribeError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
-}
-
 pub struct StoreTokenError(sqlx::Error);
 
 impl Debug for StoreTokenError {
@@ -1,28 +1,43 @@
-use std::fmt::{Debug, Formatter};
-
-use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
 use serde::Deserialize;
 use sqlx::PgPool;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-use crate::utils;
+use crate::error::AppError;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Params {
     subscription_token: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Params),
+    responses(
+        (status = 200, description = "The subscription has been confirmed"),
+        (status = 400, description = "The token is missing", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "There is no subscriber with this token", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Unexpected error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
 #[tracing::instrument("Confirming a pending subscriber", skip_all)]
 pub async fn confirm(
     params: web::Query<Params>,
     db_pool: web::Data<PgPool>,
-) -> Result<HttpResponse, ConfirmSubscriberError> {
+) -> Result<HttpResponse, AppError> {
     let subscriber_id = get_subscriber_id_from_token(&db_pool, &params.subscription_token)
         .await
         .context("Failed to retrieve the subscriber id associated with the provided token.")?
-        .ok_or(ConfirmSubscriberError::UnauthorizedError)?;
+        .ok_or_else(|| {
+            AppError::Unauthorized(
+                "There is no subscriber associated with the provided token.".to_string(),
+            )
+        })?;
 
     confirm_subscriber(&db_pool, subscriber_id)
         .await
@@ -66,26 +81,3 @@ async fn get_subscriber_id_from_token(
     })?;
     Ok(result.map(|r| r.subscription_id))
 }
-
-#[derive(thiserror::Error)]
-pub enum ConfirmSubscriberError {
-    #[error("There is no subscriber associated with the provided token.")]
-    UnauthorizedError,
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl Debug for ConfirmSubscriberError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        utils::error_chain_fmt(&self, f)
-    }
-}
-
-impl actix_web::ResponseError for ConfirmSubscriberError {
-    fn status_code(&self) -> StatusCode {
-        match self {
-            ConfirmSubscriberError::UnauthorizedError => StatusCode::UNAUTHORIZED,
-            ConfirmSubscriberError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-        }
-    }
-}
@@ -0,0 +1,33 @@
+use actix_web::http::header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+    X_FRAME_OPTIONS,
+};
+use actix_web::middleware::DefaultHeaders;
+
+use crate::config::{SecurityHeadersSettings, TlsSettings};
+
+// Headers set by a handler take precedence over these.
+// The values have been checked by `Settings::validate`.
+pub fn security_headers(
+    settings: &SecurityHeadersSettings,
+    tls: Option<&TlsSettings>,
+) -> DefaultHeaders {
+    let mut headers = DefaultHeaders::new().add((X_CONTENT_TYPE_OPTIONS, "nosniff"));
+    for (name, value) in [
+        (CONTENT_SECURITY_POLICY, &settings.content_security_policy),
+        (X_FRAME_OPTIONS, &settings.frame_options),
+        (REFERRER_POLICY, &settings.referrer_policy),
+    ] {
+        if !value.is_empty() {
+            headers = headers.add((name, value.as_str()));
+        }
+    }
+    // Only sent over HTTPS: browsers then refuse plain HTTP for the whole max-age
+    if let Some(tls) = tls {
+        headers = headers.add((
+            STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}", tls.hsts_max_age_seconds),
+        ));
+    }
+    headers
+}
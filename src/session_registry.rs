@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::config::SessionSettings;
+
+// Index of the logged-in sessions of each user, stored next to the session state in Redis.
+// `RedisSessionStore` does not let us enumerate or delete the sessions of a user, so each
+// session gets its own id and an entry here. A session whose entry is gone has been revoked.
+#[derive(Clone)]
+pub struct SessionRegistry {
+    redis: ConnectionManager,
+    key_prefix: String,
+    // Total lifetime of a session, checked by `touch`
+    ttl: chrono::Duration,
+    // Matches the state TTL of the session middleware: entries of idle sessions expire with them
+    idle_timeout: Duration,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+impl SessionRegistry {
+    pub fn new(redis: ConnectionManager, settings: &SessionSettings) -> Self {
+        Self {
+            redis,
+            key_prefix: settings.key_prefix.clone(),
+            ttl: chrono::Duration::from_std(settings.ttl()).expect("Session TTL out of range"),
+            idle_timeout: settings.idle_timeout(),
+        }
+    }
+
+    fn session_key(&self, session_id: Uuid) -> String {
+        format!("{}:session:{}", self.key_prefix, session_id)
+    }
+
+    fn user_sessions_key(&self, user_id: Uuid) -> String {
+        format!("{}:user_sessions:{}", self.key_prefix, user_id)
+    }
+
+    async fn store(&self, info: &SessionInfo) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let value = serde_json::to_string(info).context("Failed to serialize session info")?;
+        redis
+            .set_ex::<_, _, ()>(
+                self.session_key(info.session_id),
+                value,
+                self.idle_timeout.as_secs(),
+            )
+            .await
+            .context("Failed to store session info")?;
+        let user_sessions_key = self.user_sessions_key(info.user_id);
+        redis
+            .sadd::<_, _, ()>(&user_sessions_key, info.session_id.to_string())
+            .await
+            .context("Failed to index session")?;
+        redis
+            .expire::<_, ()>(&user_sessions_key, self.idle_timeout.as_secs() as i64)
+            .await
+            .context("Failed to set session index expiry")?;
+        Ok(())
+    }
+
+    async fn get(&self, session_id: Uuid) -> Result<Option<SessionInfo>, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let value: Option<String> = redis
+            .get(self.session_key(session_id))
+            .await
+            .context("Failed to read session info")?;
+        value
+            .map(|value| serde_json::from_str(&value).context("Failed to deserialize session info"))
+            .transpose()
+    }
+
+    #[tracing::instrument(name = "Register session", skip(self))]
+    pub async fn register(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<String>,
+        ip: Option<String>,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid, anyhow::Error> {
+        let info = SessionInfo {
+            session_id: Uuid::new_v4(),
+            user_id,
+            user_agent,
+            ip,
+            created_at: now,
+            last_seen: now,
+        };
+        self.store(&info).await?;
+        Ok(info.session_id)
+    }
+
+    // Records activity on a session. Returns false if the session has been revoked or has expired.
+    // An expired session is revoked, so that it disappears from the list.
+    pub async fn touch(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        now: DateTime<Utc>,
+    ) -> Result<bool, anyhow::Error> {
+        let mut info = match self.get(session_id).await? {
+            Some(info) if info.user_id == user_id => info,
+            _ => return Ok(false),
+        };
+        if now - info.created_at >= self.ttl {
+            self.revoke(user_id, session_id).await?;
+            return Ok(false);
+        }
+        info.last_seen = now;
+        let value = serde_json::to_string(&info).context("Failed to serialize session info")?;
+        // Only written back if the entry still exists (Redis' `XX` flag), so that
+        // a concurrent revocation is not undone
+        let mut redis = self.redis.clone();
+        let updated: Option<String> = redis
+            .set_options(
+                self.session_key(session_id),
+                value,
+                SetOptions::default()
+                    .conditional_set(ExistenceCheck::XX)
+                    .with_expiration(SetExpiry::EX(self.idle_timeout.as_secs() as usize)),
+            )
+            .await
+            .context("Failed to update session info")?;
+        if updated.is_none() {
+            return Ok(false);
+        }
+        redis
+            .expire::<_, ()>(
+                self.user_sessions_key(user_id),
+                self.idle_timeout.as_secs() as i64,
+            )
+            .await
+            .context("Failed to set session index expiry")?;
+        Ok(true)
+    }
+
+    // Active sessions of a user, most recently used first.
+    pub async fn list(&self, user_id: Uuid) -> Result<Vec<SessionInfo>, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let user_sessions_key = self.user_sessions_key(user_id);
+        let session_ids: Vec<String> = redis
+            .smembers(&user_sessions_key)
+            .await
+            .context("Failed to read session index")?;
+
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            let info = match Uuid::parse_str(&session_id) {
+                Ok(id) => self.get(id).await?,
+                Err(_) => None,
+            };
+            match info {
+                Some(info) => sessions.push(info),
+                // The entry has expired, drop it from the index
+                None => redis
+                    .srem::<_, _, ()>(&user_sessions_key, &session_id)
+                    .await
+                    .context("Failed to clean up session index")?,
+            }
+        }
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen));
+        Ok(sessions)
+    }
+
+    // Returns false if the user has no such session.
+    #[tracing::instrument(name = "Revoke session", skip(self))]
+    pub async fn revoke(&self, user_id: Uuid, session_id: Uuid) -> Result<bool, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let removed: u64 = redis
+            .srem(self.user_sessions_key(user_id), session_id.to_string())
+            .await
+            .context("Failed to remove session from index")?;
+        if removed == 0 {
+            return Ok(false);
+        }
+        redis
+            .del::<_, ()>(self.session_key(session_id))
+            .await
+            .context("Failed to delete session info")?;
+        Ok(true)
+    }
+
+    #[tracing::instrument(name = "Revoke other sessions", skip(self))]
+    pub async fn revoke_all_except(&self, user_id: Uuid, keep: Uuid) -> Result<(), anyhow::Error> {
+        for info in self.list(user_id).await? {
+            if info.session_id != keep {
+                self.revoke(user_id, info.session_id).await?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,15 +3,25 @@ use std::future::{ready, Ready};
 use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
+use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    // Our own id for the session, used as the key of the `SessionRegistry` entry
+    const SESSION_ID_KEY: &'static str = "session_id";
+    // Set once the password has been verified, while we wait for the second factor
+    const PENDING_SECOND_FACTOR_KEY: &'static str = "pending_second_factor_user_id";
+    const TOTP_ENROLLMENT_SECRET_KEY: &'static str = "totp_enrollment_secret";
+    const CSRF_TOKEN_KEY: &'static str = "csrf_token";
 
+    // Called whenever the privileges of the session change. The CSRF token goes too,
+    // a token obtained before logging in must not be valid afterwards.
     pub fn renew(&self) {
         self.0.renew();
+        self.0.remove(Self::CSRF_TOKEN_KEY);
     }
 
     pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
@@ -22,6 +32,53 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_second_factor(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_SECOND_FACTOR_KEY, user_id)
+    }
+
+    pub fn get_pending_second_factor(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_SECOND_FACTOR_KEY)
+    }
+
+    pub fn remove_pending_second_factor(&self) {
+        self.0.remove(Self::PENDING_SECOND_FACTOR_KEY);
+    }
+
+    pub fn insert_totp_enrollment_secret(
+        &self,
+        secret: &Secret<String>,
+    ) -> Result<(), SessionInsertError> {
+        self.0
+            .insert(Self::TOTP_ENROLLMENT_SECRET_KEY, secret.expose_secret())
+    }
+
+    pub fn get_totp_enrollment_secret(&self) -> Result<Option<Secret<String>>, SessionGetError> {
+        Ok(self
+            .0
+            .get::<String>(Self::TOTP_ENROLLMENT_SECRET_KEY)?
+            .map(Secret::new))
+    }
+
+    pub fn remove_totp_enrollment_secret(&self) {
+        self.0.remove(Self::TOTP_ENROLLMENT_SECRET_KEY);
+    }
+
+    pub fn insert_csrf_token(&self, token: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::CSRF_TOKEN_KEY, token)
+    }
+
+    pub fn get_csrf_token(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::CSRF_TOKEN_KEY)
+    }
+
     pub fn logout(&self) {
         self.0.purge();
     }
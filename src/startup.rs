@@ -1,69 +1,229 @@
+use std::collections::HashSet;
 use std::net::TcpListener;
 
-use crate::authentication::reject_anonymous_users;
+use crate::authentication::{
+    csrf_protection, reject_anonymous_users, reject_invalid_api_tokens, reject_non_owners,
+    LoginThrottle,
+};
+use actix_session::config::{BrowserSession, TtlExtensionPolicy};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
-use actix_web::cookie::Key;
+use actix_web::cookie::{self, Key, SameSite};
 use actix_web::dev::Server;
-use actix_web::{web, App, HttpServer};
+use actix_web::http::Method;
+use actix_web::{web, App, FromRequest, Handler, HttpServer, Responder, Route};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
+use anyhow::Context;
 use secrecy::ExposeSecret;
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
 use tracing_actix_web::TracingLogger;
 
-use crate::config::{DatabaseSettings, Settings};
+use crate::client_ip::TrustedProxies;
+use crate::clock::Clock;
+use crate::config::{ApplicationSettings, DatabaseSettings, Settings};
+use crate::domain::EmailPolicy;
 use crate::email_client::EmailClient;
+use crate::error::{
+    form_error_handler, json_error_handler, path_error_handler, query_error_handler, render_errors,
+};
+use crate::idempotency::idempotent;
+use crate::monitoring::{prometheus_handle, record_http_metrics};
+use crate::rate_limit::RateLimiter;
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, login,
-    login_form, logout, publish_newsletter, publish_newsletter_form, subscribe,
+    admin_dashboard, api_tokens, change_log_level, change_password, change_password_form, confirm,
+    confirm_totp_enrollment, create_api_token, create_subscriber, delete_subscriber, disable_totp,
+    get_issue, health_check, home, list_issues, list_subscribers, lockouts, log_level_form, login,
+    login_form, login_two_factor, login_two_factor_form, logout, openapi_spec, prometheus_metrics,
+    publish_issue, publish_newsletter, publish_newsletter_form, readiness, revoke_api_token,
+    revoke_session, security_form, send_newsletter_published, sessions, start_totp_enrollment,
+    subscribe, unlock, WorkerInProcess,
 };
+use crate::security_headers::security_headers;
+use crate::session_registry::SessionRegistry;
+use crate::subscription_protection::{ChallengeVerifier, SubscriptionThrottle};
+use crate::tls;
 
 pub struct Application {
     port: u16,
     server: Server,
+    metrics: Option<MetricsServer>,
+    https_redirect: Option<(u16, Server)>,
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+// Largest bodies accepted by the JSON and form extractors. The idempotency middleware
+// buffers bodies before them and applies the same limits.
+pub const JSON_BODY_LIMIT: usize = 2 * 1024 * 1024;
+pub const FORM_BODY_LIMIT: usize = 16 * 1024;
+
 impl Application {
+    // The delivery worker is expected to run in the same process, see `WorkerInProcess`
     pub async fn build(config: Settings) -> Result<Self, anyhow::Error> {
+        Self::build_with_clock(config, Clock::System).await
+    }
+
+    pub async fn build_with_clock(config: Settings, clock: Clock) -> Result<Self, anyhow::Error> {
+        Self::build_with(config, clock, WorkerInProcess(true)).await
+    }
+
+    // For an API whose delivery worker runs in processes of its own
+    pub async fn build_without_worker(config: Settings) -> Result<Self, anyhow::Error> {
+        Self::build_with(config, Clock::System, WorkerInProcess(false)).await
+    }
+
+    async fn build_with(
+        config: Settings,
+        clock: Clock,
+        worker_in_process: WorkerInProcess,
+    ) -> Result<Self, anyhow::Error> {
         let db_pool = get_db_pool(&config.database);
-        let email_client = config.email_client.client();
+        if config.database.auto_migrate {
+            migrate_database(&db_pool).await?;
+        } else {
+            ensure_schema_is_current(&db_pool).await?;
+        }
+        let email_client = config.email_client.clone().client();
 
         let address = format!("{}:{}", config.application.host, config.application.port);
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let metrics = MetricsServer::build(&config.application, db_pool.clone())?;
+        let redirect_port = config
+            .application
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.redirect_port);
+        let https_redirect = match redirect_port {
+            Some(redirect_port) => {
+                let address = format!("{}:{}", config.application.host, redirect_port);
+                let listener = TcpListener::bind(address)?;
+                let redirect_port = listener.local_addr().unwrap().port();
+                let base_url = config.application.base_url.clone();
+                Some((redirect_port, run_https_redirect(listener, base_url)?))
+            }
+            None => None,
+        };
         let server = run(
             listener,
             db_pool,
             email_client,
-            config.application.base_url,
-            config.application.hmac_secret,
-            config.redis_uri,
+            config,
+            clock,
+            worker_in_process,
         )
         .await?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            metrics,
+            https_redirect,
+        })
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+    // Once `shutdown` is cancelled, the servers stop accepting connections and wait for
+    // in-flight requests to complete, up to the configured shutdown timeout.
+    pub async fn run_until_stopped(
+        self,
+        shutdown: CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let servers: Vec<Server> = std::iter::once(self.server)
+            .chain(self.metrics.map(|metrics| metrics.server))
+            .chain(self.https_redirect.map(|(_, server)| server))
+            .collect();
+        let handles: Vec<_> = servers.iter().map(Server::handle).collect();
+        let stop = tokio::spawn(async move {
+            shutdown.cancelled().await;
+            for handle in handles {
+                handle.stop(true).await;
+            }
+        });
+        let mut running = JoinSet::new();
+        for server in servers {
+            running.spawn(server);
+        }
+        // The first server to fail brings the others down with `running`
+        let outcome = async {
+            while let Some(stopped) = running.join_next().await {
+                stopped.map_err(std::io::Error::other)??;
+            }
+            Ok(())
+        }
+        .await;
+        stop.abort();
+        outcome
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    // Set if metrics are served on their own port
+    pub fn metrics_port(&self) -> Option<u16> {
+        self.metrics.as_ref().map(MetricsServer::port)
+    }
+
+    // Set if plain HTTP is redirected to HTTPS
+    pub fn https_redirect_port(&self) -> Option<u16> {
+        self.https_redirect.as_ref().map(|(port, _)| *port)
+    }
+}
+
+// `/metrics` on `application.metrics_port`. Processes running the delivery worker without
+// the API serve it on their own, the metrics they record would be lost otherwise.
+pub struct MetricsServer {
+    port: u16,
+    server: Server,
+}
+
+impl MetricsServer {
+    // None if metrics are not to be served
+    pub fn build(
+        config: &ApplicationSettings,
+        db_pool: PgPool,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let Some(metrics_port) = config.metrics_port else {
+            return Ok(None);
+        };
+        let address = format!("{}:{}", config.host, metrics_port);
+        let listener = TcpListener::bind(address)?;
+        let port = listener.local_addr().unwrap().port();
+        let server = run_metrics(listener, db_pool)?;
+        Ok(Some(Self { port, server }))
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
+
+    pub async fn run_until_stopped(
+        self,
+        shutdown: CancellationToken,
+    ) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        let stop = tokio::spawn(async move {
+            shutdown.cancelled().await;
+            handle.stop(true).await;
+        });
+        let outcome = self.server.await;
+        stop.abort();
+        outcome
+    }
 }
 
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
-    base_url: String,
-    hmac_secret: secrecy::Secret<String>,
-    redis_url: secrecy::Secret<String>,
+    config: Settings,
+    clock: Clock,
+    worker_in_process: WorkerInProcess,
 ) -> Result<Server, anyhow::Error> {
     /*
     Use web::Data to wrap our connection pool in an ARC pointer.
@@ -71,48 +231,357 @@ pub async fn run(
      */
     let db_pool = web::Data::new(db_pool);
     let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let base_url = web::Data::new(ApplicationBaseUrl(config.application.base_url));
+    let clock = web::Data::new(clock);
+    let worker_in_process = web::Data::new(worker_in_process);
+    let idempotency_settings = web::Data::new(config.idempotency.clone());
+    let shutdown_timeout = config.application.shutdown_timeout_seconds;
+    let tls_config = config
+        .application
+        .tls
+        .as_ref()
+        .map(tls::server_config)
+        .transpose()?;
+    let security_headers_settings = config.security_headers.clone();
+    let tls_settings = config.application.tls.clone();
 
     // Setup message framework for flash messages (using cookies)
-    let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
+    let secret_key = Key::from(config.application.hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
 
-    let redis_store = RedisSessionStore::new(redis_url.expose_secret()).await?;
+    let redis_store = RedisSessionStore::new(config.redis_uri.expose_secret()).await?;
+    let redis_connection = redis::Client::open(config.redis_uri.expose_secret().as_str())?
+        .get_connection_manager()
+        .await?;
+    let login_throttle = web::Data::new(LoginThrottle::new(
+        RateLimiter::new(
+            redis_connection.clone(),
+            config.login_throttling.key_prefix.clone(),
+        ),
+        &config.login_throttling,
+    ));
+    let subscription_throttle = web::Data::new(SubscriptionThrottle::new(
+        RateLimiter::new(
+            redis_connection.clone(),
+            config.subscription_protection.key_prefix.clone(),
+        ),
+        &config.subscription_protection,
+    ));
+    let challenge_verifier = config
+        .subscription_protection
+        .challenge
+        .as_ref()
+        .map(|settings| web::Data::new(ChallengeVerifier::new(settings)));
+    let email_policy = web::Data::new(EmailPolicy::from_settings(&config.subscriber_email)?);
+    let trusted_proxies =
+        web::Data::new(TrustedProxies(config.application.trusted_proxies.clone()));
+    let redis_connection_data = web::Data::new(redis_connection.clone());
+    let session_registry = web::Data::new(SessionRegistry::new(redis_connection, &config.sessions));
+    let cookie_secure = config.sessions.cookie_secure;
+    let idle_timeout = cookie::time::Duration::seconds(config.sessions.idle_timeout_seconds as i64);
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(actix_web_lab::middleware::from_fn(render_errors))
+            .wrap(actix_web_lab::middleware::from_fn(record_http_metrics))
             .wrap(TracingLogger::default())
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
-            .route("/", web::get().to(home))
-            .route("/health_check", web::get().to(health_check))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .cookie_secure(cookie_secure)
+                    .cookie_http_only(true)
+                    .cookie_same_site(SameSite::Strict)
+                    // The session cookie dies with the browser, its state after a period
+                    // of inactivity. `SessionRegistry` enforces the total lifetime.
+                    .session_lifecycle(
+                        BrowserSession::default()
+                            .state_ttl(idle_timeout)
+                            .state_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest),
+                    )
+                    .build(),
+            )
+            .configure(register(public_endpoints()))
+            // Subscriptions only require a challenge if a verifier is configured
+            .configure(|config| {
+                if let Some(challenge_verifier) = &challenge_verifier {
+                    config.app_data(challenge_verifier.clone());
+                }
+            })
             .service(
-                web::scope("/admin")
+                web::scope(LOGIN_SCOPE)
+                    .wrap(actix_web_lab::middleware::from_fn(csrf_protection))
+                    .configure(register(login_endpoints())),
+            )
+            .service(
+                web::scope(ADMIN_SCOPE)
+                    .wrap(actix_web_lab::middleware::from_fn(csrf_protection))
                     .wrap(actix_web_lab::middleware::from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/logout", web::post().to(logout))
-                    .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password)),
+                    .configure(register(admin_endpoints())),
             )
+            .service(
+                web::scope(API_V1_SCOPE)
+                    .wrap(actix_web_lab::middleware::from_fn(
+                        reject_invalid_api_tokens,
+                    ))
+                    .configure(register(api_v1_endpoints())),
+            )
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(JSON_BODY_LIMIT)
+                    .error_handler(json_error_handler),
+            )
+            .app_data(
+                web::FormConfig::default()
+                    .limit(FORM_BODY_LIMIT)
+                    .error_handler(form_error_handler),
+            )
+            .app_data(web::PathConfig::default().error_handler(path_error_handler))
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(clock.clone())
+            .app_data(worker_in_process.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(login_throttle.clone())
+            .app_data(subscription_throttle.clone())
+            .app_data(email_policy.clone())
+            .app_data(session_registry.clone())
+            .app_data(redis_connection_data.clone())
+            .app_data(idempotency_settings.clone())
+            .wrap(security_headers(
+                &security_headers_settings,
+                tls_settings.as_ref(),
+            ))
     })
+    // Signals are handled by the caller, which stops the background tasks too
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout);
+    let server = match tls_config {
+        Some(tls_config) => server.listen_rustls_0_23(listener, tls_config)?,
+        None => server.listen(listener)?,
+    };
+
+    Ok(server.run())
+}
+
+// Redirects every plain HTTP request to the same path on `base_url`
+fn run_https_redirect(listener: TcpListener, base_url: String) -> Result<Server, anyhow::Error> {
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let server = HttpServer::new(move || {
+        App::new()
+            .default_service(web::to(tls::redirect_to_https))
+            .app_data(base_url.clone())
+    })
+    .disable_signals()
+    .listen(listener)?
+    .run();
+
+    Ok(server)
+}
+
+// Serves metrics only. They are never served on the main port: queue depth, pool usage
+// and provider errors are nobody else's business.
+fn run_metrics(listener: TcpListener, db_pool: PgPool) -> Result<Server, anyhow::Error> {
+    let db_pool = web::Data::new(db_pool);
+    let prometheus_handle = web::Data::new(prometheus_handle());
+    let server = HttpServer::new(move || {
+        App::new()
+            .configure(register(metrics_endpoints()))
+            .app_data(db_pool.clone())
+            .app_data(prometheus_handle.clone())
+    })
+    .disable_signals()
     .listen(listener)?
     .run();
 
     Ok(server)
 }
 
+// Routes are grouped by scope, each scope with its own middleware stack.
+// Keeping them in tables lets us list every registered route, e.g. to check that
+// the OpenAPI spec documents all of them.
+const LOGIN_SCOPE: &str = "/login";
+const ADMIN_SCOPE: &str = "/admin";
+const API_V1_SCOPE: &str = "/api/v1";
+
+pub struct Endpoint {
+    pub method: Method,
+    pub path: &'static str,
+    route: Route,
+}
+
+impl Endpoint {
+    fn new<F, Args>(method: Method, path: &'static str, handler: F) -> Self
+    where
+        F: Handler<Args>,
+        Args: FromRequest + 'static,
+        F::Output: Responder + 'static,
+    {
+        Self {
+            route: web::method(method.clone()).to(handler),
+            method,
+            path,
+        }
+    }
+
+    // For admin routes that only owners may use
+    fn owner_only(mut self) -> Self {
+        self.route = self
+            .route
+            .wrap(actix_web_lab::middleware::from_fn(reject_non_owners));
+        self
+    }
+
+    // See `idempotency::idempotent`
+    fn idempotent(mut self, on_replay: Option<fn()>) -> Self {
+        self.route = self
+            .route
+            .wrap(actix_web_lab::middleware::from_fn(move |req, next| {
+                idempotent(req, next, on_replay)
+            }));
+        self
+    }
+}
+
+fn register(endpoints: Vec<Endpoint>) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |config| {
+        for endpoint in endpoints {
+            config.route(endpoint.path, endpoint.route);
+        }
+    }
+}
+
+fn public_endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint::new(Method::GET, "/", home),
+        Endpoint::new(Method::GET, "/health_check", health_check),
+        Endpoint::new(Method::GET, "/health/ready", readiness),
+        Endpoint::new(Method::POST, "/subscriptions", subscribe),
+        Endpoint::new(Method::GET, "/subscriptions/confirm", confirm),
+        Endpoint::new(Method::GET, "/api/openapi.json", openapi_spec),
+    ]
+}
+
+fn metrics_endpoints() -> Vec<Endpoint> {
+    vec![Endpoint::new(Method::GET, "/metrics", prometheus_metrics)]
+}
+
+fn login_endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint::new(Method::GET, "", login_form),
+        Endpoint::new(Method::POST, "", login),
+        Endpoint::new(Method::GET, "/two-factor", login_two_factor_form),
+        Endpoint::new(Method::POST, "/two-factor", login_two_factor),
+    ]
+}
+
+fn admin_endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint::new(Method::GET, "/dashboard", admin_dashboard),
+        Endpoint::new(Method::POST, "/logout", logout),
+        Endpoint::new(Method::GET, "/newsletters", publish_newsletter_form),
+        Endpoint::new(Method::POST, "/newsletters", publish_newsletter)
+            .idempotent(Some(send_newsletter_published)),
+        Endpoint::new(Method::GET, "/password", change_password_form),
+        Endpoint::new(Method::POST, "/password", change_password),
+        Endpoint::new(Method::GET, "/security", security_form),
+        Endpoint::new(Method::POST, "/security/totp", start_totp_enrollment),
+        Endpoint::new(
+            Method::POST,
+            "/security/totp/confirm",
+            confirm_totp_enrollment,
+        ),
+        Endpoint::new(Method::POST, "/security/totp/disable", disable_totp),
+        Endpoint::new(Method::GET, "/api-tokens", api_tokens),
+        Endpoint::new(Method::POST, "/api-tokens", create_api_token),
+        Endpoint::new(Method::POST, "/api-tokens/revoke", revoke_api_token),
+        Endpoint::new(Method::GET, "/sessions", sessions),
+        Endpoint::new(Method::POST, "/sessions/revoke", revoke_session),
+        Endpoint::new(Method::GET, "/lockouts", lockouts).owner_only(),
+        Endpoint::new(Method::POST, "/lockouts/unlock", unlock).owner_only(),
+        Endpoint::new(Method::GET, "/log-level", log_level_form).owner_only(),
+        Endpoint::new(Method::POST, "/log-level", change_log_level).owner_only(),
+    ]
+}
+
+fn api_v1_endpoints() -> Vec<Endpoint> {
+    vec![
+        Endpoint::new(Method::GET, "/issues", list_issues),
+        Endpoint::new(Method::POST, "/issues", publish_issue).idempotent(None),
+        Endpoint::new(Method::GET, "/issues/{issue_id}", get_issue),
+        Endpoint::new(Method::GET, "/subscribers", list_subscribers),
+        Endpoint::new(Method::POST, "/subscribers", create_subscriber),
+        Endpoint::new(
+            Method::DELETE,
+            "/subscribers/{subscriber_id}",
+            delete_subscriber,
+        ),
+    ]
+}
+
+// Method and full path of every route served by `run`, and by `run_metrics`.
+pub fn registered_routes() -> Vec<(Method, String)> {
+    let groups = [
+        (String::new(), public_endpoints()),
+        (String::new(), metrics_endpoints()),
+        (LOGIN_SCOPE.to_string(), login_endpoints()),
+        (ADMIN_SCOPE.to_string(), admin_endpoints()),
+        (API_V1_SCOPE.to_string(), api_v1_endpoints()),
+    ];
+    groups
+        .into_iter()
+        .flat_map(|(prefix, endpoints)| {
+            endpoints
+                .into_iter()
+                .map(move |endpoint| (endpoint.method, format!("{}{}", prefix, endpoint.path)))
+        })
+        .collect()
+}
+
+// The migrations this binary was built with
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+// sqlx holds a Postgres advisory lock while it applies migrations, so replicas starting
+// at the same time wait for each other instead of racing.
+pub async fn migrate_database(db_pool: &PgPool) -> Result<(), anyhow::Error> {
+    MIGRATOR
+        .run(db_pool)
+        .await
+        .context("Failed to migrate the database")
+}
+
+// Versions of the migrations this binary was built with that were not applied successfully
+pub async fn pending_migrations(db_pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied: HashSet<i64> =
+        sqlx::query_scalar!("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(db_pool)
+            .await?
+            .into_iter()
+            .collect();
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.contains(&m.version))
+        .map(|m| m.version)
+        .collect())
+}
+
+// Serving requests against an outdated schema would fail in confusing ways
+async fn ensure_schema_is_current(db_pool: &PgPool) -> Result<(), anyhow::Error> {
+    let pending = pending_migrations(db_pool)
+        .await
+        .context("Failed to read the applied migrations")?;
+    if !pending.is_empty() {
+        let versions: Vec<String> = pending.iter().map(ToString::to_string).collect();
+        anyhow::bail!(
+            "The database schema is behind, pending migrations: {}. \
+            Run the `migrate` command or set `database.auto_migrate`",
+            versions.join(", ")
+        );
+    }
+    Ok(())
+}
+
 pub fn get_db_pool(config: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
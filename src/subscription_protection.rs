@@ -0,0 +1,116 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+
+use crate::config::{ChallengeSettings, SubscriptionProtectionSettings};
+use crate::domain::SubscriberEmail;
+use crate::rate_limit::{Limit, RateLimiter};
+
+const IP_KEY_PREFIX: &str = "subscriptions:ip:";
+const EMAIL_DOMAIN_KEY_PREFIX: &str = "subscriptions:domain:";
+
+// Counts subscriptions per client IP and per email domain.
+// Unlike login failures, every subscription counts since each one sends an email.
+#[derive(Clone)]
+pub struct SubscriptionThrottle {
+    limiter: RateLimiter,
+    per_ip: Limit,
+    per_email_domain: Limit,
+}
+
+impl SubscriptionThrottle {
+    pub fn new(limiter: RateLimiter, settings: &SubscriptionProtectionSettings) -> Self {
+        Self {
+            limiter,
+            per_ip: Limit {
+                max_hits: settings.max_per_ip,
+                window: settings.window(),
+            },
+            per_email_domain: Limit {
+                max_hits: settings.max_per_email_domain,
+                window: settings.window(),
+            },
+        }
+    }
+
+    // Counts a subscription attempt and returns how long the caller must wait if it
+    // goes over a limit. Refused attempts count too, the window does not move.
+    #[tracing::instrument(name = "Count subscription attempt", skip(self, email))]
+    pub async fn attempt(
+        &self,
+        email: &SubscriberEmail,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut retry_after = self
+            .limiter
+            .hit(&email_domain_key(email), self.per_email_domain)
+            .await?;
+        if let Some(ip) = ip {
+            let ip_retry_after = self.limiter.hit(&ip_key(ip), self.per_ip).await?;
+            retry_after = retry_after.max(ip_retry_after);
+        }
+        Ok(retry_after)
+    }
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("{}{}", IP_KEY_PREFIX, ip)
+}
+
+fn email_domain_key(email: &SubscriberEmail) -> String {
+    format!("{}{}", EMAIL_DOMAIN_KEY_PREFIX, email.domain())
+}
+
+// Checks the token produced by a challenge widget embedded in the subscription form
+#[derive(Clone)]
+pub struct ChallengeVerifier {
+    http_client: reqwest::Client,
+    verify_url: String,
+    secret: Secret<String>,
+}
+
+#[derive(Deserialize)]
+struct VerifyResponse {
+    success: bool,
+}
+
+impl ChallengeVerifier {
+    pub fn new(settings: &ChallengeSettings) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.timeout())
+            .build()
+            .expect("Could not create ChallengeVerifier");
+        Self {
+            http_client,
+            verify_url: settings.verify_url.clone(),
+            secret: settings.secret.clone(),
+        }
+    }
+
+    #[tracing::instrument(name = "Verify subscription challenge", skip(self, response))]
+    pub async fn verify(&self, response: &str, ip: Option<IpAddr>) -> Result<bool, anyhow::Error> {
+        let mut form = vec![
+            ("secret", self.secret.expose_secret().to_string()),
+            ("response", response.to_string()),
+        ];
+        if let Some(ip) = ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let outcome: VerifyResponse = self
+            .http_client
+            .post(&self.verify_url)
+            .form(&form)
+            .send()
+            .await
+            .context("Failed to reach the challenge verifier")?
+            .error_for_status()
+            .context("The challenge verifier returned an error")?
+            .json()
+            .await
+            .context("Failed to read the challenge verifier response")?;
+        Ok(outcome.success)
+    }
+}
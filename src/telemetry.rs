@@ -1,31 +1,231 @@
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{self, Tracer};
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use tokio::task::JoinHandle;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
 use tracing_log::LogTracer;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::MakeWriter;
-use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+use tracing_subscriber::{layer::SubscriberExt, reload, EnvFilter, Registry};
 
+// Spans are always logged as bunyan JSON lines. With a `tracer` they are also exported
+// to an OpenTelemetry collector, see `otlp_tracer`.
+// The returned `LogFilter` changes the level filter of the subscriber while it runs.
 pub fn get_subscriber<Sink>(
     name: &str,
     default_log_level: &str,
     sink: Sink,
-) -> impl Subscriber + Send + Sync
+    tracer: Option<Tracer>,
+) -> (impl Subscriber + Send + Sync, LogFilter)
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_log_level));
+    let default_directives = env_filter.to_string();
+    let (env_filter, handle) = reload::Layer::new(env_filter);
     let formatting_layer = BunyanFormattingLayer::new(name.to_string(), sink);
-    Registry::default()
+    let subscriber = Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)));
+    let log_filter = LogFilter {
+        handle,
+        default_directives,
+        foreign_targets: Vec::new(),
+        state: Arc::new(Mutex::new(OverrideState::default())),
+    };
+    (subscriber, log_filter)
 }
 
-pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
+// `log_filter` becomes available through `log_filter()`
+pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync, log_filter: LogFilter) {
     LogTracer::init().expect("Failed to set logger");
     set_global_default(subscriber).expect("Failed to set tracing subscriber");
+    init_trace_context_propagation();
+    LOG_FILTER
+        .set(log_filter)
+        .unwrap_or_else(|_| panic!("The global subscriber has already been set"));
+}
+
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+// The filter of the global subscriber, if it has been initialised
+pub fn log_filter() -> Option<&'static LogFilter> {
+    LOG_FILTER.get()
+}
+
+// Lets owners turn up the logs of a module during an incident, without a restart.
+// Every change is reverted to the startup directives after a while, so that
+// a forgotten debug level does not keep flooding the logs.
+// The filter only applies to the process serving the admin area: a worker started on its
+// own keeps the directives of its `RUST_LOG` until it is restarted.
+#[derive(Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    default_directives: String,
+    // Modules that run in another process, directives targeting them are rejected
+    foreign_targets: Vec<&'static str>,
+    state: Arc<Mutex<OverrideState>>,
+}
+
+#[derive(Default)]
+struct OverrideState {
+    // Bumped on every change, so that the revert of an earlier change does nothing
+    generation: u64,
+    reverts_at: Option<Instant>,
+}
+
+impl LogFilter {
+    pub fn directives(&self) -> String {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn default_directives(&self) -> &str {
+        &self.default_directives
+    }
+
+    // For processes that leave some modules to another one, e.g. the API without the worker
+    pub fn with_foreign_targets(mut self, targets: &[&'static str]) -> Self {
+        self.foreign_targets.extend_from_slice(targets);
+        self
+    }
+
+    pub fn foreign_targets(&self) -> &[&'static str] {
+        &self.foreign_targets
+    }
+
+    // Time left before the current directives are reverted, if they have been changed
+    pub fn reverts_in(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state
+            .reverts_at
+            .map(|at| at.saturating_duration_since(Instant::now()))
+    }
+
+    // Must be called from within a Tokio runtime, which runs the revert
+    pub fn set(&self, directives: &str, revert_after: Duration) -> Result<(), anyhow::Error> {
+        let filter = EnvFilter::try_new(directives).context("Invalid filter directives")?;
+        if let Some(target) = foreign_target(directives, &self.foreign_targets) {
+            anyhow::bail!(
+                "{} does not run in this process, its log level cannot be changed from here",
+                target
+            );
+        }
+        let mut state = self.state.lock().unwrap();
+        self.handle
+            .reload(filter)
+            .context("Failed to reload the log filter")?;
+        state.generation += 1;
+        state.reverts_at = Some(Instant::now() + revert_after);
+        let generation = state.generation;
+        let log_filter = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(revert_after).await;
+            log_filter.revert(generation);
+        });
+        Ok(())
+    }
+
+    fn revert(&self, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        match self.handle.reload(EnvFilter::new(&self.default_directives)) {
+            Ok(()) => {
+                state.reverts_at = None;
+                tracing::info!("Log filter reverted to {}", self.default_directives);
+            }
+            Err(e) => tracing::error!(
+                error.message = %e,
+                "Failed to revert the log filter"
+            ),
+        }
+    }
+}
+
+// The first directive whose target is, or is within, one of `foreign_targets`.
+// A directive is `target[span{field=value}]=level`, where everything but the target is optional.
+fn foreign_target<'a>(directives: &str, foreign_targets: &[&'a str]) -> Option<&'a str> {
+    directives.split(',').find_map(|directive| {
+        let target = directive
+            .split(['[', '='])
+            .next()
+            .unwrap_or_default()
+            .trim();
+        foreign_targets.iter().copied().find(|foreign| {
+            target
+                .strip_prefix(foreign)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+    })
+}
+
+// Exports spans over OTLP/HTTP in batches. Must be called from within a Tokio runtime.
+pub fn otlp_tracer(service_name: &str, endpoint: &str) -> Result<Tracer, TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .install_batch(runtime::Tokio)
+}
+
+// Flushes the spans that have not been exported yet.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+// Incoming requests continue the trace of their W3C `traceparent` header (see `TracingLogger`)
+// and outgoing calls carry the current one, see `trace_context_headers`.
+pub fn init_trace_context_propagation() {
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+// Headers carrying the trace context of the current span, empty if spans are not exported.
+pub fn trace_context_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
 }
 
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
@@ -36,3 +236,40 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::foreign_target;
+
+    const WORKER: &str = "zero2prod::issue_delivery_worker";
+
+    #[test]
+    fn directives_targeting_a_foreign_module_are_found() {
+        for directives in [
+            "zero2prod::issue_delivery_worker=debug",
+            "info,zero2prod::issue_delivery_worker=debug",
+            "zero2prod::issue_delivery_worker::retry=trace",
+            "zero2prod::issue_delivery_worker[task]=debug",
+            " zero2prod::issue_delivery_worker ",
+        ] {
+            assert_eq!(
+                foreign_target(directives, &[WORKER]),
+                Some(WORKER),
+                "{directives}"
+            );
+        }
+    }
+
+    #[test]
+    fn other_directives_are_accepted() {
+        for directives in [
+            "debug",
+            "info,zero2prod=debug",
+            "zero2prod::routes=debug",
+            "zero2prod::issue_delivery_worker_pool=debug",
+            "[issue_delivery_worker]=debug",
+        ] {
+            assert_eq!(foreign_target(directives, &[WORKER]), None, "{directives}");
+        }
+    }
+}
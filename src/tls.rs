@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use actix_web::http::header::LOCATION;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use rustls::ServerConfig;
+
+use crate::config::TlsSettings;
+use crate::startup::ApplicationBaseUrl;
+
+// Clients negotiate HTTP/2 or HTTP/1.1 through ALPN, which actix-web sets up
+pub fn server_config(settings: &TlsSettings) -> Result<ServerConfig, anyhow::Error> {
+    let certs = rustls_pemfile::certs(&mut open(&settings.cert_path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to read the certificates in {}", settings.cert_path))?;
+    let key = rustls_pemfile::private_key(&mut open(&settings.key_path)?)
+        .with_context(|| format!("Failed to read the private key in {}", settings.key_path))?
+        .with_context(|| format!("No private key in {}", settings.key_path))?;
+    ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or private key")
+}
+
+fn open(path: &str) -> Result<BufReader<File>, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    Ok(BufReader::new(file))
+}
+
+// Served over plain HTTP, a 308 keeps the method and body of the request
+pub async fn redirect_to_https(
+    req: HttpRequest,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> HttpResponse {
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    HttpResponse::PermanentRedirect()
+        .insert_header((
+            LOCATION,
+            format!("{}{}", base_url.0.trim_end_matches('/'), path),
+        ))
+        .finish()
+}
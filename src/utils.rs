@@ -14,25 +14,24 @@ pub fn error_chain_fmt(e: &impl Error, f: &mut Formatter<'_>) -> std::fmt::Resul
     Ok(())
 }
 
-// Return an opaque 500 while preserving the error's root cause for logging.
-pub fn error_500<T>(e: T) -> actix_web::Error
-where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
-{
-    actix_web::error::ErrorInternalServerError(e)
-}
-
-// Return a 400 with the user-representation of the validation error as body.
-// The error root cause is preserved for logging purposes.
-pub fn error_400<T>(e: T) -> actix_web::Error
-where
-    T: std::fmt::Debug + std::fmt::Display + 'static,
-{
-    actix_web::error::ErrorBadRequest(e)
-}
-
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Escape user-controlled values before interpolating them into our HTML templates.
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,113 @@
+use reqwest::Method;
+
+use crate::helpers::spawn_app;
+use crate::utils::assert_redirect_is_to;
+
+#[tokio::test]
+async fn requests_without_a_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/v1/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["detail"]
+        .as_str()
+        .unwrap()
+        .contains("Missing bearer token"));
+}
+
+#[tokio::test]
+async fn requests_with_an_unknown_token_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_v1(Method::GET, "/issues", "z2p_not-a-real-token")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_session_cookie_is_not_enough_to_use_the_api() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/v1/issues", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn tokens_are_shown_once_and_stored_hashed() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    let response = app
+        .api_v1(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("Test token"));
+    assert!(!html_page.contains(&token));
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn a_revoked_token_is_rejected() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+
+    let response = app
+        .post_revoke_api_token(&serde_json::json!({ "api_token_id": api_token_id }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("<p><i>The API token has been revoked</i></p>"));
+    assert!(html_page.contains("You have no API tokens."));
+
+    let response = app
+        .api_v1(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_token_needs_a_name() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_create_api_token(&serde_json::json!({ "name": "  " }))
+        .await;
+
+    assert_redirect_is_to(&response, "/admin/api-tokens");
+    let html_page = app.get_api_tokens_html().await;
+    assert!(html_page.contains("The token name must be between 1 and 100 characters long"));
+}
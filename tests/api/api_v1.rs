@@ -0,0 +1,479 @@
+use reqwest::Method;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+fn issue_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "text_content": "Newsletter body as plain text",
+    })
+}
+
+async fn create_subscriber(app: &TestApp, token: &str) -> serde_json::Value {
+    let _mock = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .api_v1(Method::POST, "/subscribers", token)
+        .json(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "name": "le guin",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+async fn confirm_all_subscribers(app: &TestApp) {
+    sqlx::query!("UPDATE subscription SET status = 'confirmed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn publishing_an_issue_enqueues_deliveries_and_reports_progress() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    create_subscriber(&app, &token).await;
+    confirm_all_subscribers(&app).await;
+
+    let response = app
+        .api_v1(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let status_url = body["status_url"].as_str().unwrap().to_string();
+
+    let status: serde_json::Value = app
+        .api_v1(Method::GET, &status_url["/api/v1".len()..], &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["pending_deliveries"], 1);
+    assert_eq!(status["status"], "in_progress");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let status: serde_json::Value = app
+        .api_v1(Method::GET, &status_url["/api/v1".len()..], &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["pending_deliveries"], 0);
+    assert_eq!(status["failed_deliveries"], 0);
+    assert_eq!(status["status"], "delivered");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    create_subscriber(&app, &token).await;
+    confirm_all_subscribers(&app).await;
+    let response = app
+        .api_v1(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    let status_url = body["status_url"].as_str().unwrap().to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let status: serde_json::Value = app
+        .api_v1(Method::GET, &status_url["/api/v1".len()..], &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(status["pending_deliveries"], 0);
+    assert_eq!(status["failed_deliveries"], 1);
+    assert_eq!(status["status"], "completed_with_failures");
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let mut issue_ids = Vec::new();
+    for _ in 0..2 {
+        let response = app
+            .api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&issue_body())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 202);
+        let body: serde_json::Value = response.json().await.unwrap();
+        issue_ids.push(body["issue_id"].clone());
+    }
+
+    assert_eq!(issue_ids[0], issue_ids[1]);
+    let issues: serde_json::Value = app
+        .api_v1(Method::GET, "/issues", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issues["issues"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn concurrent_duplicate_publishes_return_the_same_issue() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+
+    let publish = || {
+        app.api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&issue_body())
+            .send()
+    };
+    let (response1, response2) = tokio::join!(publish(), publish());
+    let (response1, response2) = (response1.unwrap(), response2.unwrap());
+
+    assert_eq!(response1.status().as_u16(), 202);
+    assert_eq!(response2.status().as_u16(), 202);
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+}
+
+#[tokio::test]
+async fn publishing_while_the_same_key_is_in_flight_returns_409() {
+    let app = spawn_app_with(|c| c.idempotency.in_flight_wait_milliseconds = 200).await;
+    let token = app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let mut in_flight = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&mut *in_flight)
+    .await
+    .unwrap();
+
+    let response = app
+        .api_v1(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", &idempotency_key)
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "1");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["detail"],
+        "A request with the same idempotency key is still being processed"
+    );
+    in_flight.rollback().await.unwrap();
+}
+
+#[tokio::test]
+async fn publishing_requires_an_idempotency_key() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    let response = app
+        .api_v1(Method::POST, "/issues", &token)
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["detail"],
+        "Missing idempotency key, send it in the Idempotency-Key header \
+        or the idempotency_key form field"
+    );
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_a_different_issue_is_rejected() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let publish = |body: serde_json::Value| {
+        app.api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&body)
+            .send()
+    };
+
+    let response = publish(issue_body()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let mut other_issue = issue_body();
+    other_issue["title"] = "Another title".into();
+    let response = publish(other_issue).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 422);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["detail"],
+        "The idempotency key has already been used for a different request"
+    );
+}
+
+#[tokio::test]
+async fn failed_requests_do_not_use_up_their_idempotency_key() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    let publish = |body: serde_json::Value| {
+        app.api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", &idempotency_key)
+            .json(&body)
+            .send()
+    };
+
+    let mut invalid_issue = issue_body();
+    invalid_issue["title"] = "".into();
+    let response = publish(invalid_issue).await.unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+    let response = publish(issue_body()).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn invalid_bodies_are_rejected_with_a_json_error() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "title": "Newsletter!" }),
+            "missing content",
+        ),
+        (
+            serde_json::json!({ "title": "", "html_content": "<p>Hi</p>", "text_content": "Hi" }),
+            "empty title",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app
+            .api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", Uuid::new_v4().to_string())
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            description
+        );
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert!(body["detail"].is_string());
+    }
+}
+
+#[tokio::test]
+async fn issue_bodies_are_limited_by_the_json_config_only() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let publish = |size: usize| {
+        let mut body = issue_body();
+        body["html_content"] = format!("<p>{}</p>", "a".repeat(size)).into();
+        app.api_v1(Method::POST, "/issues", &token)
+            .header("Idempotency-Key", Uuid::new_v4().to_string())
+            .json(&body)
+            .send()
+    };
+
+    // Over the 256 KB that actix-web accepts by default
+    let response = publish(512 * 1024).await.unwrap();
+    assert_eq!(response.status().as_u16(), 202);
+    let response = publish(3 * 1024 * 1024).await.unwrap();
+    assert_eq!(response.status().as_u16(), 413);
+}
+
+#[tokio::test]
+async fn unknown_issues_are_not_found() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    for issue_id in [Uuid::new_v4().to_string(), "not-a-uuid".to_string()] {
+        let response = app
+            .api_v1(Method::GET, &format!("/issues/{}", issue_id), &token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_be_created_listed_and_deleted() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    let subscriber = create_subscriber(&app, &token).await;
+    assert_eq!(subscriber["status"], "pending_confirmation");
+
+    let list: serde_json::Value = app
+        .api_v1(
+            Method::GET,
+            "/subscribers?status=pending_confirmation",
+            &token,
+        )
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["subscribers"][0]["email"], "ursula_le_guin@gmail.com");
+    // The creation response reports the stored timestamp
+    assert_eq!(
+        list["subscribers"][0]["subscribed_at"],
+        subscriber["subscribed_at"]
+    );
+    let list: serde_json::Value = app
+        .api_v1(Method::GET, "/subscribers?status=confirmed", &token)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(list["subscribers"].as_array().unwrap().is_empty());
+
+    let subscriber_path = format!("/subscribers/{}", subscriber["id"].as_str().unwrap());
+    let response = app
+        .api_v1(Method::DELETE, &subscriber_path, &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .api_v1(Method::DELETE, &subscriber_path, &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn creating_an_existing_subscriber_returns_it() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let subscriber = create_subscriber(&app, &token).await;
+    confirm_all_subscribers(&app).await;
+
+    let response = app
+        .api_v1(Method::POST, "/subscribers", &token)
+        .json(&serde_json::json!({
+            "email": "Ursula_Le_Guin@gmail.com",
+            "name": "ursula",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let existing: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(existing["id"], subscriber["id"]);
+    assert_eq!(existing["name"], "le guin");
+    assert_eq!(existing["status"], "confirmed");
+}
+
+#[tokio::test]
+async fn invalid_subscribers_are_rejected() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    let response = app
+        .api_v1(Method::POST, "/subscribers", &token)
+        .json(&serde_json::json!({ "email": "definitely-not-an-email", "name": "le guin" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .api_v1(Method::GET, "/subscribers?status=unsubscribed", &token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_do_not_receive_pending_issues() {
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+    let subscriber = create_subscriber(&app, &token).await;
+    confirm_all_subscribers(&app).await;
+    app.api_v1(Method::POST, "/issues", &token)
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&issue_body())
+        .send()
+        .await
+        .unwrap();
+
+    let subscriber_path = format!("/subscribers/{}", subscriber["id"].as_str().unwrap());
+    app.api_v1(Method::DELETE, &subscriber_path, &token)
+        .send()
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+}
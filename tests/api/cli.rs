@@ -0,0 +1,87 @@
+use reqwest::StatusCode;
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::authentication::{create_user, Role};
+use zero2prod::issue_delivery_worker::queue_stats;
+
+use crate::helpers::spawn_app;
+use crate::newsletters::create_confirmed_subscriber;
+use crate::utils::assert_redirect_is_to;
+
+#[tokio::test]
+async fn created_users_can_log_in_with_their_role() {
+    // Arrange
+    let app = spawn_app().await;
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    create_user(
+        &username,
+        Secret::new(password.clone()),
+        Role::Editor,
+        &app.db_pool,
+    )
+    .await
+    .unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": password
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+    assert_eq!(app.get_log_level().await.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn usernames_are_unique() {
+    // Arrange
+    let app = spawn_app().await;
+    let password = Secret::new(Uuid::new_v4().to_string());
+
+    // Act
+    let outcome = create_user(&app.test_user.username, password, Role::Owner, &app.db_pool).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn queue_stats_count_the_pending_deliveries_of_each_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    assert!(queue_stats(&app.db_pool).await.unwrap().is_empty());
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+
+    // Assert
+    let stats = queue_stats(&app.db_pool).await.unwrap();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].title, "Newsletter title");
+    assert_eq!(stats[0].pending_deliveries, 1);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    assert!(queue_stats(&app.db_pool).await.unwrap().is_empty());
+}
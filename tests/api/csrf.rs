@@ -0,0 +1,136 @@
+use crate::helpers::{extract_csrf_token, spawn_app};
+use crate::utils::assert_redirect_is_to;
+
+#[tokio::test]
+async fn post_forms_carry_the_session_csrf_token() {
+    let app = spawn_app().await;
+
+    let login_page = app.get_login_html().await;
+    let token = extract_csrf_token(&login_page);
+    assert_eq!(token.len(), 32);
+
+    // The token is stable for the lifetime of the session
+    let login_page = app.get_login_html().await;
+    assert_eq!(extract_csrf_token(&login_page), token);
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(r#"name="csrf_token""#));
+}
+
+#[tokio::test]
+async fn login_without_a_csrf_token_is_rejected() {
+    let app = spawn_app().await;
+    app.get_login_html().await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_csrf_token_can_be_submitted_as_a_form_field() {
+    let app = spawn_app().await;
+    let token = extract_csrf_token(&app.get_login_html().await);
+
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "csrf_token": token
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_redirect_is_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn admin_forms_with_a_wrong_csrf_token_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+            "old_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_confirm": &new_password,
+            "csrf_token": "not-the-session-token"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    // The password was not changed
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_token_from_another_session_is_rejected() {
+    let app = spawn_app().await;
+    let other_session_token = extract_csrf_token(
+        &reqwest::get(format!("{}/login", &app.address))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap(),
+    );
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", other_session_token)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_in_rotates_the_csrf_token() {
+    let app = spawn_app().await;
+    let token_before_login = extract_csrf_token(&app.get_login_html().await);
+    app.test_user.login(&app).await;
+
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .header("X-CSRF-Token", &token_before_login)
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 403);
+    let token_after_login = extract_csrf_token(&app.get_change_password_html().await);
+    assert_ne!(token_after_login, token_before_login);
+}
@@ -0,0 +1,138 @@
+use reqwest::Method;
+use uuid::Uuid;
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn api_errors_are_problem_details_with_the_request_id() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    // Act
+    let response = app
+        .api_v1(Method::GET, &format!("/issues/{}", Uuid::new_v4()), &token)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let request_id = response.headers()["X-Request-Id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["type"], "about:blank");
+    assert_eq!(body["title"], "Not Found");
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["detail"], "There is no newsletter issue with this id");
+    assert_eq!(body["request_id"], request_id.as_str());
+}
+
+#[tokio::test]
+async fn browsers_get_an_html_error_page_with_the_request_id() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            &app.address
+        ))
+        .header("Accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let request_id = response.headers()["X-Request-Id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("There is no subscriber associated with the provided token."));
+    assert!(html_page.contains(&request_id));
+}
+
+#[tokio::test]
+async fn errors_raised_by_middlewares_are_rendered_too() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - No CSRF token
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], 403);
+    assert!(body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn unexpected_errors_do_not_leak_their_cause() {
+    // Arrange
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE subscription DROP COLUMN email;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["detail"], "Something went wrong");
+    assert!(!body.to_string().contains("column"));
+}
+
+#[tokio::test]
+async fn unknown_routes_are_rendered_like_other_errors() {
+    // Arrange
+    let app = spawn_app().await;
+    let get = |accept: &'static str| {
+        app.api_client
+            .get(format!("{}/there/is/nothing/here", &app.address))
+            .header("Accept", accept)
+            .send()
+    };
+
+    // Act
+    let api_response = get("application/json").await.unwrap();
+    let browser_response = get("text/html").await.unwrap();
+
+    // Assert
+    assert_eq!(api_response.status().as_u16(), 404);
+    assert_eq!(
+        api_response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+    let body: serde_json::Value = api_response.json().await.unwrap();
+    assert_eq!(body["title"], "Not Found");
+    assert!(body["request_id"].is_string());
+    assert_eq!(browser_response.status().as_u16(), 404);
+    let html_page = browser_response.text().await.unwrap();
+    assert!(html_page.contains("<h1>404 Not Found</h1>"));
+}
@@ -1,4 +1,8 @@
-use crate::helpers::spawn_app;
+use tokio_util::sync::CancellationToken;
+use zero2prod::issue_delivery_worker;
+use zero2prod::startup::Application;
+
+use crate::helpers::{config_with_empty_db, spawn_app, TestApp};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -14,3 +18,124 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(response.content_length(), Some(0));
 }
+
+async fn get_readiness(test_app: &TestApp) -> (reqwest::StatusCode, serde_json::Value) {
+    let response = test_app
+        .api_client
+        .get(format!("{}/health/ready", test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute GET health/ready");
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn readiness_reports_every_component_as_up() {
+    let test_app = spawn_app().await;
+    issue_delivery_worker::record_heartbeat(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let (status, body) = get_readiness(&test_app).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "up");
+    for component in ["database", "redis", "migrations", "worker"] {
+        assert_eq!(body[component]["status"], "up", "{} is down", component);
+    }
+}
+
+#[tokio::test]
+async fn readiness_fails_if_the_worker_has_never_reported() {
+    let test_app = spawn_app().await;
+
+    let (status, body) = get_readiness(&test_app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "down");
+    assert_eq!(body["worker"]["status"], "down");
+    assert_eq!(body["database"]["status"], "up");
+}
+
+#[tokio::test]
+async fn readiness_fails_if_the_worker_heartbeat_is_stale() {
+    let test_app = spawn_app().await;
+    issue_delivery_worker::record_heartbeat(&test_app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE worker_heartbeat SET last_seen_at = now() - interval '5 minutes'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let (status, body) = get_readiness(&test_app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["worker"]["status"], "down");
+}
+
+#[tokio::test]
+async fn readiness_fails_if_migrations_are_pending() {
+    let test_app = spawn_app().await;
+    issue_delivery_worker::record_heartbeat(&test_app.db_pool)
+        .await
+        .unwrap();
+    let latest: i64 = sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let (status, body) = get_readiness(&test_app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["migrations"]["status"], "down");
+    assert!(body["migrations"]["error"]
+        .as_str()
+        .unwrap()
+        .contains(&latest.to_string()));
+}
+
+#[tokio::test]
+async fn readiness_does_not_leak_database_errors() {
+    let test_app = spawn_app().await;
+    sqlx::query("DROP TABLE worker_heartbeat")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    let (status, body) = get_readiness(&test_app).await;
+
+    assert_eq!(status, 503);
+    assert_eq!(body["worker"]["error"], "unavailable");
+    assert!(!body.to_string().contains("worker_heartbeat"));
+}
+
+#[tokio::test]
+async fn readiness_ignores_a_worker_running_in_another_process() {
+    // Arrange - The worker has never reported
+    let mut config = config_with_empty_db().await;
+    config.database.auto_migrate = true;
+    let application = Application::build_without_worker(config)
+        .await
+        .expect("Failed to build application");
+    let address = format!("http://127.0.0.1:{}", application.port());
+    let shutdown = CancellationToken::new();
+    tokio::spawn(application.run_until_stopped(shutdown.clone()));
+
+    // Act
+    let response = reqwest::get(format!("{}/health/ready", address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body.get("worker").is_none());
+    shutdown.cancel();
+}
@@ -2,10 +2,13 @@ use std::sync::Once;
 
 use argon2::password_hash::SaltString;
 use argon2::{Argon2, PasswordHasher};
+use chrono::{TimeZone, Utc};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 use wiremock::MockServer;
 
+use zero2prod::clock::Clock;
 use zero2prod::config::DatabaseSettings;
 use zero2prod::email_client::EmailClient;
 use zero2prod::startup::get_db_pool;
@@ -18,11 +21,15 @@ static TRACING: Once = Once::new();
 pub struct TestApp {
     pub address: String,
     pub port: u16,
+    pub metrics_port: Option<u16>,
+    pub https_redirect_port: Option<u16>,
     pub db_pool: PgPool,
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub clock: Clock,
+    pub shutdown: CancellationToken,
 }
 
 pub struct TestUser {
@@ -58,12 +65,25 @@ impl TestApp {
             .expect("Failed to execute POST subscribe")
     }
 
+    // The token of the current session, taken from a page with a POST form.
+    // The dashboard does not consume flash messages, so it is preferred when logged in.
+    pub async fn csrf_token(&self) -> String {
+        let response = self.get_admin_dashboard().await;
+        let html_page = if response.status().as_u16() == 200 {
+            response.text().await.unwrap()
+        } else {
+            self.get_login_html().await
+        };
+        extract_csrf_token(&html_page)
+    }
+
     pub async fn post_login<T>(&self, body: &T) -> reqwest::Response
     where
         T: serde::Serialize,
     {
         self.api_client
             .post(format!("{}/login", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -104,6 +124,7 @@ impl TestApp {
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/logout", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .send()
             .await
             .expect("Failed to execute POST logout")
@@ -119,6 +140,7 @@ impl TestApp {
     {
         self.api_client
             .post(format!("{}/admin/password", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
@@ -143,12 +165,248 @@ impl TestApp {
     ) -> reqwest::Response {
         self.api_client
             .post(format!("{}/admin/newsletters", self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
             .form(body)
             .send()
             .await
             .expect("Could not POST /admin/newsletters")
     }
 
+    pub async fn get_login_two_factor(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/login/two-factor", self.address))
+            .send()
+            .await
+            .expect("Could not GET /login/two-factor")
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.get_login_two_factor().await.text().await.unwrap()
+    }
+
+    pub async fn post_login_two_factor<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login/two-factor", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST login two-factor")
+    }
+
+    pub async fn get_security_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/security", self.address))
+            .send()
+            .await
+            .expect("Could not GET /admin/security")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_start_totp_enrollment(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/security/totp", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .send()
+            .await
+            .expect("Failed to execute POST start TOTP enrollment")
+    }
+
+    pub async fn post_confirm_totp_enrollment<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/security/totp/confirm", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST confirm TOTP enrollment")
+    }
+
+    pub async fn post_disable_totp<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/security/totp/disable", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST disable TOTP")
+    }
+
+    pub async fn get_lockouts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/lockouts", self.address))
+            .send()
+            .await
+            .expect("Could not GET /admin/lockouts")
+    }
+
+    pub async fn get_lockouts_html(&self) -> String {
+        self.get_lockouts().await.text().await.unwrap()
+    }
+
+    pub async fn get_log_level(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/log-level", self.address))
+            .send()
+            .await
+            .expect("Could not GET /admin/log-level")
+    }
+
+    pub async fn get_log_level_html(&self) -> String {
+        self.get_log_level().await.text().await.unwrap()
+    }
+
+    pub async fn post_log_level<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/log-level", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST log-level")
+    }
+
+    pub async fn post_unlock<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/lockouts/unlock", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST unlock")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/sessions", self.address))
+            .send()
+            .await
+            .expect("Could not GET /admin/sessions")
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.get_sessions().await.text().await.unwrap()
+    }
+
+    pub async fn post_revoke_session<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/sessions/revoke", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST revoke session")
+    }
+
+    pub async fn get_api_tokens_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/api-tokens", self.address))
+            .send()
+            .await
+            .expect("Could not GET /admin/api-tokens")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_api_token<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api-tokens", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST create API token")
+    }
+
+    pub async fn post_revoke_api_token<T>(&self, body: &T) -> reqwest::Response
+    where
+        T: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/admin/api-tokens/revoke", &self.address))
+            .header("X-CSRF-Token", self.csrf_token().await)
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute POST revoke API token")
+    }
+
+    // Log in as the test user and create an API token for them
+    pub async fn create_api_token(&self) -> String {
+        self.test_user.login(self).await;
+        let html_page = self
+            .post_create_api_token(&serde_json::json!({ "name": "Test token" }))
+            .await
+            .text()
+            .await
+            .unwrap();
+        let start = html_page
+            .find("<code>z2p_")
+            .expect("No API token on the page")
+            + 6;
+        let end = start + html_page[start..].find("</code>").unwrap();
+        html_page[start..end].to_string()
+    }
+
+    // A request to the JSON API, authenticated with a bearer token and without cookies
+    pub fn api_v1(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: &str,
+    ) -> reqwest::RequestBuilder {
+        reqwest::Client::new()
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(token)
+    }
+
+    pub async fn get_metrics(&self) -> String {
+        let metrics_port = self.metrics_port.expect("Metrics are not served");
+        self.api_client
+            .get(format!("http://127.0.0.1:{}/metrics", metrics_port))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_openapi_spec(&self) -> serde_json::Value {
+        self.api_client
+            .get(format!("{}/api/openapi.json", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .json()
+            .await
+            .unwrap()
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -175,6 +433,16 @@ impl TestApp {
     }
 }
 
+pub fn extract_csrf_token(html_page: &str) -> String {
+    let marker = r#"name="csrf_token" value=""#;
+    let start = html_page
+        .find(marker)
+        .expect("The page does not contain a CSRF token")
+        + marker.len();
+    let end = start + html_page[start..].find('"').unwrap();
+    html_page[start..end].to_string()
+}
+
 impl TestUser {
     pub fn generate() -> Self {
         Self {
@@ -192,7 +460,8 @@ impl TestUser {
             .to_string();
         sqlx::query!(
             r#"
-                INSERT INTO users(user_id, username, password) VALUES ($1, $2, $3)
+                INSERT INTO users(user_id, username, password, role)
+                VALUES ($1, $2, $3, 'owner')
             "#,
             self.user_id,
             self.username,
@@ -203,6 +472,17 @@ impl TestUser {
         .expect("Failed to insert test user");
     }
 
+    pub async fn set_role(&self, role: &str, db_pool: &PgPool) {
+        sqlx::query!(
+            "UPDATE users SET role = $2 WHERE user_id = $1",
+            self.user_id,
+            role
+        )
+        .execute(db_pool)
+        .await
+        .expect("Failed to update test user role");
+    }
+
     pub async fn login(&self, test_app: &TestApp) {
         test_app
             .post_login(&serde_json::json!({
@@ -214,18 +494,31 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// Spawn the application after applying test-specific tweaks to the configuration
+pub async fn spawn_app_with(configure: impl FnOnce(&mut config::Settings)) -> TestApp {
     TRACING.call_once(|| {
         let default_filter_level = "info";
         let subscriber_name = "zero2prod - test";
 
         if std::env::var("TEST_LOG").is_ok() {
-            let subscriber =
-                telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
-            telemetry::init_subscriber(subscriber);
+            let (subscriber, log_filter) = telemetry::get_subscriber(
+                subscriber_name,
+                default_filter_level,
+                std::io::stdout,
+                None,
+            );
+            telemetry::init_subscriber(subscriber, log_filter);
         } else {
-            let subscriber =
-                telemetry::get_subscriber(subscriber_name, default_filter_level, std::io::sink);
-            telemetry::init_subscriber(subscriber);
+            let (subscriber, log_filter) = telemetry::get_subscriber(
+                subscriber_name,
+                default_filter_level,
+                std::io::sink,
+                None,
+            );
+            telemetry::init_subscriber(subscriber, log_filter);
         }
     });
 
@@ -234,22 +527,38 @@ pub async fn spawn_app() -> TestApp {
 
     // Randomise config to ensure test isolation
     let config = {
-        let mut c = config::get_config().expect("Failed to read config file");
+        let mut c = config::load_config(config::Environment::TEST, None)
+            .expect("Failed to read config file");
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.application.metrics_port = Some(0);
         c.email_client.base_url = email_server.uri();
+        // Redis is shared between tests, keep each app's counters apart
+        c.login_throttling.key_prefix = Uuid::new_v4().to_string();
+        c.subscription_protection.key_prefix = Uuid::new_v4().to_string();
+        c.sessions.key_prefix = Uuid::new_v4().to_string();
+        configure(&mut c);
         c
     };
 
     // Create and migrate the database
     configure_db(&config.database).await;
 
+    // Freeze time so that tests can compute the same TOTP codes as the application
+    let clock = Clock::fixed(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap());
+
     // Launch the application as a background task
-    let application = Application::build(config.clone())
+    let application = Application::build_with_clock(config.clone(), clock.clone())
         .await
         .expect("Failed to build application");
     let application_port = application.port();
-    let address = format!("http://127.0.0.1:{}", application_port);
+    let metrics_port = application.metrics_port();
+    let https_redirect_port = application.https_redirect_port();
+    let scheme = match config.application.tls {
+        Some(_) => "https",
+        None => "http",
+    };
+    let address = format!("{}://127.0.0.1:{}", scheme, application_port);
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -257,32 +566,28 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .expect("Could not create reqwest Client");
 
-    tokio::spawn(application.run_until_stopped());
+    let shutdown = CancellationToken::new();
+    tokio::spawn(application.run_until_stopped(shutdown.clone()));
 
     let test_app = TestApp {
         address,
         port: application_port,
+        metrics_port,
+        https_redirect_port,
         db_pool: get_db_pool(&config.database),
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
         email_client: config.email_client.client(),
+        clock,
+        shutdown,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
 async fn configure_db(settings: &DatabaseSettings) -> PgPool {
-    // Create DB
-    let mut connection = PgConnection::connect_with(&settings.without_db())
-        .await
-        .expect("Failed to connect to postgres");
-
-    tracing::info!("Creating database with name {}", &settings.database_name);
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, settings.database_name).as_str())
-        .await
-        .expect("Failed to create database");
+    create_db(settings).await;
 
     // Migrate DB
     let db_pool = PgPool::connect_with(settings.with_db())
@@ -294,3 +599,25 @@ async fn configure_db(settings: &DatabaseSettings) -> PgPool {
         .expect("Failed to migrate database");
     db_pool
 }
+
+async fn create_db(settings: &DatabaseSettings) {
+    let mut connection = PgConnection::connect_with(&settings.without_db())
+        .await
+        .expect("Failed to connect to postgres");
+
+    tracing::info!("Creating database with name {}", &settings.database_name);
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, settings.database_name).as_str())
+        .await
+        .expect("Failed to create database");
+}
+
+// Configuration pointing to a new database without any table, not even sqlx's own
+pub async fn config_with_empty_db() -> config::Settings {
+    let mut config =
+        config::load_config(config::Environment::TEST, None).expect("Failed to read config file");
+    config.database.database_name = Uuid::new_v4().to_string();
+    config.application.port = 0;
+    create_db(&config.database).await;
+    config
+}
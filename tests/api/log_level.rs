@@ -0,0 +1,77 @@
+use reqwest::StatusCode;
+
+use crate::helpers::spawn_app;
+use crate::utils::assert_redirect_is_to;
+
+// The filter is global to the test process: this is the only test that changes it
+#[tokio::test]
+async fn owners_can_change_the_log_level_until_it_reverts() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let html_page = app.get_log_level_html().await;
+    assert!(html_page.contains("These are the startup directives."));
+
+    // Act - Part 1 - Change the filter
+    let response = app
+        .post_log_level(&serde_json::json!({
+            "directives": "info,zero2prod::issue_delivery_worker=debug",
+            "revert_after_seconds": 1,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/log-level");
+
+    // Assert - Part 1
+    let html_page = app.get_log_level_html().await;
+    assert!(html_page.contains("The log filter has been changed, it will be reverted in 1s"));
+    assert!(html_page
+        .contains("Current directives: <code>zero2prod::issue_delivery_worker=debug,info</code>"));
+
+    // Act - Part 2 - Wait for the revert
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    // Assert - Part 2
+    let html_page = app.get_log_level_html().await;
+    assert!(html_page.contains("These are the startup directives."));
+    assert!(html_page.contains("Current directives: <code>info</code>"));
+}
+
+#[tokio::test]
+async fn invalid_directives_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_log_level(&serde_json::json!({
+            "directives": "zero2prod=loudest",
+            "revert_after_seconds": 60,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/log-level");
+
+    // Assert
+    let html_page = app.get_log_level_html().await;
+    assert!(html_page.contains("Invalid filter directives"));
+}
+
+#[tokio::test]
+async fn editors_cannot_change_the_log_level() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.set_role("editor", &app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_log_level(&serde_json::json!({
+            "directives": "debug",
+            "revert_after_seconds": 60,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(app.get_log_level().await.status(), StatusCode::FORBIDDEN);
+}
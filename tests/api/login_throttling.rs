@@ -0,0 +1,179 @@
+use reqwest::StatusCode;
+
+use crate::helpers::{spawn_app_with, TestApp};
+use crate::utils::assert_redirect_is_to;
+
+async fn spawn_throttled_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.login_throttling.max_failures_per_username = 3;
+        c.login_throttling.max_failures_per_ip = 5;
+    })
+    .await
+}
+
+async fn fail_login(app: &TestApp, username: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": username,
+        "password": "wrong-password"
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn a_username_is_locked_out_after_too_many_failures() {
+    let app = spawn_throttled_app().await;
+
+    for _ in 0..3 {
+        let response = fail_login(&app, &app.test_user.username).await;
+        assert_redirect_is_to(&response, "/login");
+    }
+
+    // Even the right password is refused while locked out
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 900);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Too many failed login attempts"));
+}
+
+#[tokio::test]
+async fn an_ip_is_locked_out_after_too_many_failures_across_usernames() {
+    let app = spawn_throttled_app().await;
+
+    for _ in 0..5 {
+        let response = fail_login(&app, &uuid::Uuid::new_v4().to_string()).await;
+        assert_redirect_is_to(&response, "/login");
+    }
+
+    let response = fail_login(&app, &uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn concurrent_failures_cannot_exceed_the_limit() {
+    let app = spawn_throttled_app().await;
+    let csrf_token = app.csrf_token().await;
+
+    let mut requests = tokio::task::JoinSet::new();
+    for _ in 0..10 {
+        let request = app
+            .api_client
+            .post(format!("{}/login", app.address))
+            .header("X-CSRF-Token", &csrf_token)
+            .form(&serde_json::json!({
+                "username": app.test_user.username,
+                "password": "wrong-password"
+            }));
+        requests.spawn(async move { request.send().await.unwrap().status() });
+    }
+
+    let mut verified = 0;
+    while let Some(status) = requests.join_next().await {
+        if status.unwrap() != StatusCode::TOO_MANY_REQUESTS {
+            verified += 1;
+        }
+    }
+    assert_eq!(verified, 3);
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_username_counter() {
+    let app = spawn_throttled_app().await;
+
+    for _ in 0..2 {
+        fail_login(&app, &app.test_user.username).await;
+    }
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    for _ in 0..2 {
+        let response = fail_login(&app, &app.test_user.username).await;
+        assert_redirect_is_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn owners_can_see_and_lift_lockouts() {
+    let app = spawn_throttled_app().await;
+    let locked_out_username = uuid::Uuid::new_v4().to_string();
+    for _ in 0..3 {
+        fail_login(&app, &locked_out_username).await;
+    }
+
+    app.test_user.login(&app).await;
+    let html_page = app.get_lockouts_html().await;
+    assert!(html_page.contains(&locked_out_username));
+
+    let response = app
+        .post_unlock(&serde_json::json!({
+            "kind": "username",
+            "subject": &locked_out_username,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/lockouts");
+    let html_page = app.get_lockouts_html().await;
+    assert!(html_page.contains(&format!("{} has been unlocked", locked_out_username)));
+    assert!(html_page.contains("Nobody is locked out."));
+}
+
+#[tokio::test]
+async fn editors_cannot_see_lockouts() {
+    let app = spawn_throttled_app().await;
+    app.test_user.set_role("editor", &app.db_pool).await;
+    app.test_user.login(&app).await;
+
+    let response = app.get_lockouts().await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_lockouts() {
+    let app = spawn_throttled_app().await;
+
+    let response = app.get_lockouts().await;
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_throttled_separately() {
+    let app = spawn_app_with(|c| {
+        c.login_throttling.max_failures_per_ip = 2;
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    let csrf_token = app.csrf_token().await;
+    let fail_login_from = |client_ip: &'static str| {
+        app.api_client
+            .post(format!("{}/login", app.address))
+            .header("X-CSRF-Token", &csrf_token)
+            .header("X-Forwarded-For", client_ip)
+            .form(&serde_json::json!({
+                "username": uuid::Uuid::new_v4().to_string(),
+                "password": "wrong-password"
+            }))
+            .send()
+    };
+
+    for _ in 0..2 {
+        fail_login_from("203.0.113.7").await.unwrap();
+    }
+    let attacker = fail_login_from("203.0.113.7").await.unwrap();
+    let other_client = fail_login_from("198.51.100.20").await.unwrap();
+
+    assert_eq!(attacker.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_redirect_is_to(&other_client, "/login");
+}
@@ -1,9 +1,25 @@
 mod admin_dashboard;
+mod api_tokens;
+mod api_v1;
 mod change_password;
+mod cli;
+mod csrf;
+mod errors;
 mod health_check;
 mod helpers;
+mod log_level;
 mod login;
+mod login_throttling;
+mod metrics;
+mod migrations;
 mod newsletters;
+mod openapi;
+mod security_headers;
+mod sessions;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod telemetry;
+mod tls;
+mod two_factor;
 mod utils;
@@ -0,0 +1,226 @@
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::config;
+use zero2prod::startup::MetricsServer;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+// The recorder is shared by every test of the process, so tests look for series
+// rather than for exact counts.
+fn has_series(metrics: &str, name: &str, labels: &[&str]) -> bool {
+    metrics
+        .lines()
+        .any(|line| line.starts_with(name) && labels.iter().all(|label| line.contains(label)))
+}
+
+async fn enqueue_delivery(app: &TestApp, subscriber_email: &str) {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue (
+            newsletter_issue_id, title, text_content, html_content, published_at
+        )
+        VALUES ($1, 'Title', 'Text', '<p>HTML</p>', now())
+        "#,
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        VALUES ($1, $2)
+        "#,
+        issue_id,
+        subscriber_email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn metrics_are_served_in_the_prometheus_format() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "http://127.0.0.1:{}/metrics",
+            app.metrics_port.unwrap()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+}
+
+#[tokio::test]
+async fn requests_are_counted_and_timed_per_route() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.create_api_token().await;
+
+    // Act
+    app.api_v1(
+        reqwest::Method::GET,
+        &format!("/issues/{}", Uuid::new_v4()),
+        &token,
+    )
+    .send()
+    .await
+    .unwrap();
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    let route = r#"route="/api/v1/issues/{issue_id}""#;
+    assert!(has_series(
+        &metrics,
+        "http_requests_total",
+        &[r#"method="GET""#, route, r#"status="404""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "http_request_duration_seconds_bucket",
+        &[r#"method="GET""#, route]
+    ));
+}
+
+#[tokio::test]
+async fn queue_depth_and_pool_usage_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    enqueue_delivery(&app, "ursula_le_guin@gmail.com").await;
+    enqueue_delivery(&app, "octavia_butler@gmail.com").await;
+
+    // Act
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(metrics
+        .lines()
+        .any(|line| line == "issue_delivery_queue_depth 2"));
+    assert!(has_series(
+        &metrics,
+        "db_pool_connections",
+        &[r#"state="idle""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "db_pool_connections",
+        &[r#"state="in_use""#]
+    ));
+    assert!(has_series(&metrics, "db_pool_max_connections", &[]));
+}
+
+#[tokio::test]
+async fn deliveries_and_email_provider_responses_are_counted() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    enqueue_delivery(&app, "ursula_le_guin@gmail.com").await;
+    enqueue_delivery(&app, "not-an-email").await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+    let metrics = app.get_metrics().await;
+
+    // Assert
+    assert!(has_series(
+        &metrics,
+        "email_deliveries_total",
+        &[r#"outcome="failure""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "email_deliveries_total",
+        &[r#"outcome="invalid_address""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "email_client_responses_total",
+        &[r#"status="500""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "email_delivery_duration_seconds_bucket",
+        &[]
+    ));
+}
+
+#[tokio::test]
+async fn metrics_are_never_served_on_the_main_port() {
+    for metrics_port in [Some(0), None] {
+        // Arrange
+        let app = spawn_app_with(|c| c.application.metrics_port = metrics_port).await;
+
+        // Act
+        let response = app
+            .api_client
+            .get(format!("{}/metrics", app.address))
+            .send()
+            .await
+            .unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 404);
+        assert_eq!(app.metrics_port.is_some(), metrics_port.is_some());
+    }
+}
+
+#[tokio::test]
+async fn a_worker_running_on_its_own_serves_its_delivery_counters() {
+    // Arrange - The API serves no metrics, the worker's server must
+    let app = spawn_app_with(|c| c.application.metrics_port = None).await;
+    let mut config = config::load_config(config::Environment::TEST, None).unwrap();
+    config.application.metrics_port = Some(0);
+    let worker_metrics = MetricsServer::build(&config.application, app.db_pool.clone())
+        .unwrap()
+        .expect("Metrics are served");
+    let metrics_port = worker_metrics.port();
+    tokio::spawn(worker_metrics.run_until_stopped(app.shutdown.clone()));
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    enqueue_delivery(&app, "ursula_le_guin@gmail.com").await;
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+    let metrics = app
+        .api_client
+        .get(format!("http://127.0.0.1:{}/metrics", metrics_port))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(has_series(
+        &metrics,
+        "email_deliveries_total",
+        &[r#"outcome="success""#]
+    ));
+    assert!(has_series(
+        &metrics,
+        "email_client_responses_total",
+        &[r#"status="200""#]
+    ));
+}
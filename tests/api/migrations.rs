@@ -0,0 +1,269 @@
+use std::borrow::Cow;
+
+use chrono::{Duration, Utc};
+use sqlx::migrate::Migrator;
+use sqlx::PgPool;
+use uuid::Uuid;
+use zero2prod::startup::{
+    get_db_pool, migrate_database, pending_migrations, Application, MIGRATOR,
+};
+
+use crate::helpers::config_with_empty_db;
+
+#[tokio::test]
+async fn the_application_migrates_the_database_when_auto_migrate_is_on() {
+    // Arrange
+    let mut config = config_with_empty_db().await;
+    config.database.auto_migrate = true;
+
+    // Act
+    Application::build(config.clone())
+        .await
+        .expect("Failed to build application");
+
+    // Assert
+    let db_pool = get_db_pool(&config.database);
+    assert!(pending_migrations(&db_pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_start_on_an_unmigrated_database() {
+    // Arrange
+    let mut config = config_with_empty_db().await;
+    config.database.auto_migrate = false;
+
+    // Act
+    let outcome = Application::build(config).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn the_application_refuses_to_start_if_migrations_are_pending() {
+    // Arrange
+    let mut config = config_with_empty_db().await;
+    config.database.auto_migrate = false;
+    let db_pool = get_db_pool(&config.database);
+    migrate_database(&db_pool).await.unwrap();
+    let latest: i64 = sqlx::query_scalar("SELECT max(version) FROM _sqlx_migrations")
+        .fetch_one(&db_pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM _sqlx_migrations WHERE version = $1")
+        .bind(latest)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let error = Application::build(config)
+        .await
+        .err()
+        .expect("The application started with a pending migration");
+
+    // Assert
+    assert!(error.to_string().contains(&latest.to_string()));
+}
+
+#[tokio::test]
+async fn replicas_starting_together_migrate_the_database_once() {
+    // Arrange
+    let mut config = config_with_empty_db().await;
+    config.database.auto_migrate = true;
+
+    // Act
+    let (first, second) = tokio::join!(
+        Application::build(config.clone()),
+        Application::build(config.clone())
+    );
+
+    // Assert
+    assert!(first.is_ok());
+    assert!(second.is_ok());
+    let db_pool = get_db_pool(&config.database);
+    assert!(pending_migrations(&db_pool).await.unwrap().is_empty());
+}
+
+// Applies the migrations older than `version`, to seed data the way it was stored back then
+async fn migrate_up_to(db_pool: &PgPool, version: i64) {
+    let migrator = Migrator {
+        migrations: Cow::Owned(
+            MIGRATOR
+                .migrations
+                .iter()
+                .filter(|migration| migration.version < version)
+                .cloned()
+                .collect(),
+        ),
+        ..Migrator::DEFAULT
+    };
+    migrator.run(db_pool).await.unwrap();
+}
+
+async fn insert_subscriber(
+    db_pool: &PgPool,
+    email: &str,
+    status: &str,
+    subscribed_days_ago: i64,
+    token: &str,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO subscription (id, email, name, subscribed_at, status) \
+        VALUES ($1, $2, 'name', $3, $4)",
+    )
+    .bind(id)
+    .bind(email)
+    .bind(Utc::now() - Duration::days(subscribed_days_ago))
+    .bind(status)
+    .execute(db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO subscription_token (subscription_token, subscription_id) VALUES ($1, $2)",
+    )
+    .bind(token)
+    .bind(id)
+    .execute(db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+#[tokio::test]
+async fn subscriptions_differing_only_by_case_are_merged() {
+    // Arrange
+    let config = config_with_empty_db().await;
+    let db_pool = get_db_pool(&config.database);
+    migrate_up_to(&db_pool, 20261018150000).await;
+    // The confirmed subscription is kept over an older pending one
+    let alice_pending = insert_subscriber(
+        &db_pool,
+        "Alice@Example.com",
+        "pending_confirmation",
+        2,
+        "a1",
+    )
+    .await;
+    let alice_confirmed =
+        insert_subscriber(&db_pool, "alice@example.com", "confirmed", 1, "a2").await;
+    // Otherwise the oldest is kept
+    let bob_oldest =
+        insert_subscriber(&db_pool, "Bob@Example.com", "pending_confirmation", 2, "b1").await;
+    let bob_newest =
+        insert_subscriber(&db_pool, "bob@example.com", "pending_confirmation", 1, "b2").await;
+    // Surrounding whitespace is not part of the address either
+    let carol_oldest = insert_subscriber(
+        &db_pool,
+        " Carol@Example.com ",
+        "pending_confirmation",
+        2,
+        "c1",
+    )
+    .await;
+    let carol_newest = insert_subscriber(
+        &db_pool,
+        "carol@example.com",
+        "pending_confirmation",
+        1,
+        "c2",
+    )
+    .await;
+    let issue_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO newsletter_issue \
+        (newsletter_issue_id, title, text_content, html_content, published_at) \
+        VALUES ($1, 'title', 'text', 'html', now())",
+    )
+    .bind(issue_id)
+    .execute(&db_pool)
+    .await
+    .unwrap();
+    for email in ["Alice@Example.com", "alice@example.com"] {
+        sqlx::query(
+            "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email) \
+            VALUES ($1, $2)",
+        )
+        .bind(issue_id)
+        .bind(email)
+        .execute(&db_pool)
+        .await
+        .unwrap();
+    }
+
+    // Act
+    migrate_database(&db_pool).await.unwrap();
+
+    // Assert
+    let subscriptions: Vec<(Uuid, String)> =
+        sqlx::query_as("SELECT id, email FROM subscription ORDER BY email")
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        subscriptions,
+        vec![
+            (alice_confirmed, "alice@example.com".to_string()),
+            (bob_oldest, "bob@example.com".to_string()),
+            (carol_oldest, "carol@example.com".to_string()),
+        ]
+    );
+    let tokens: Vec<(String, Uuid)> = sqlx::query_as(
+        "SELECT subscription_token, subscription_id FROM subscription_token \
+        ORDER BY subscription_token",
+    )
+    .fetch_all(&db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        tokens,
+        vec![
+            ("a1".to_string(), alice_confirmed),
+            ("a2".to_string(), alice_confirmed),
+            ("b1".to_string(), bob_oldest),
+            ("b2".to_string(), bob_oldest),
+            ("c1".to_string(), carol_oldest),
+            ("c2".to_string(), carol_oldest),
+        ]
+    );
+    let queue: Vec<(Uuid, String)> =
+        sqlx::query_as("SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_queue")
+            .fetch_all(&db_pool)
+            .await
+            .unwrap();
+    assert_eq!(queue, vec![(issue_id, "alice@example.com".to_string())]);
+    let merges: Vec<(Uuid, Uuid, String, String, String)> = sqlx::query_as(
+        "SELECT merged_id, kept_id, merged_email, kept_email, merged_status \
+        FROM merged_subscription ORDER BY lower(trim(kept_email))",
+    )
+    .fetch_all(&db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        merges,
+        vec![
+            (
+                alice_pending,
+                alice_confirmed,
+                "Alice@Example.com".to_string(),
+                "alice@example.com".to_string(),
+                "pending_confirmation".to_string(),
+            ),
+            (
+                bob_newest,
+                bob_oldest,
+                "bob@example.com".to_string(),
+                "Bob@Example.com".to_string(),
+                "pending_confirmation".to_string(),
+            ),
+            (
+                carol_newest,
+                carol_oldest,
+                "carol@example.com".to_string(),
+                " Carol@Example.com ".to_string(),
+                "pending_confirmation".to_string(),
+            ),
+        ]
+    );
+}
@@ -1,4 +1,4 @@
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{spawn_app, spawn_app_with, ConfirmationLinks, TestApp};
 use crate::utils::assert_redirect_is_to;
 use fake::faker::internet::en::SafeEmail;
 use fake::faker::name::en::Name;
@@ -236,6 +236,231 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     // Mock verifies on Drop that we have sent the newsletter email only once
 }
 
+#[tokio::test]
+async fn a_duplicate_submission_gives_up_waiting_for_an_in_flight_one_with_a_409() {
+    // Arrange
+    let app = spawn_app_with(|c| c.idempotency.in_flight_wait_milliseconds = 200).await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": idempotency_key
+    });
+
+    // Hold the key the way a slow in-flight request would
+    let mut in_flight = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&mut *in_flight)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Submit while the key is held
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "1");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["detail"],
+        "A request with the same idempotency key is still being processed"
+    );
+
+    // Act - Part 2 - The in-flight request fails, so a retry goes through
+    in_flight.rollback().await.unwrap();
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn a_key_without_a_saved_response_is_reported_as_in_progress() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    sqlx::query!(
+        "INSERT INTO idempotency (user_id, idempotency_key, created_at) VALUES ($1, $2, now())",
+        app.test_user.user_id,
+        idempotency_key
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(response.headers()["Retry-After"], "1");
+}
+
+#[tokio::test]
+async fn reusing_an_idempotency_key_for_a_different_issue_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let idempotency_key = uuid::Uuid::new_v4().to_string();
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Another title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": idempotency_key
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 422);
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that only the first issue has been sent
+}
+
+#[tokio::test]
+async fn a_form_resubmitted_from_a_new_session_is_replayed() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+
+    // Act - The new session comes with a new CSRF token
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    assert_redirect_is_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the newsletter has been sent only once
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_treated_as_new() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Reuse the key after it has expired
+    sqlx::query!("UPDATE idempotency SET created_at = now() - interval '2 days'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that the newsletter has been sent twice
+}
+
+#[tokio::test]
+async fn the_cleanup_job_deletes_expired_idempotency_keys_only() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let keys: Vec<String> = (0..3).map(|_| uuid::Uuid::new_v4().to_string()).collect();
+    for key in &keys {
+        let response = app
+            .post_publish_newsletter(&serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": key
+            }))
+            .await;
+        assert_redirect_is_to(&response, "/admin/newsletters");
+    }
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '2 days' WHERE idempotency_key <> $1",
+        keys[0]
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - A batch size of 1 makes the job go through several batches
+    let deleted =
+        zero2prod::idempotency::delete_expired_keys(&app.db_pool, Duration::from_secs(86400), 1)
+            .await
+            .unwrap();
+
+    // Assert
+    assert_eq!(deleted, 2);
+    let remaining = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].idempotency_key, keys[0]);
+}
+
 /*#[tokio::test]
 async fn transient_errors_do_not_cause_duplicate_deliveries_on_retries() {
     // Arrange
@@ -315,7 +540,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     app.get_confirmation_links(email_request)
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
+pub async fn create_confirmed_subscriber(app: &TestApp) {
     let confirmation_link = create_unconfirmed_subscriber(app).await;
 
     reqwest::get(confirmation_link.html)
@@ -0,0 +1,101 @@
+use std::collections::HashSet;
+
+use zero2prod::startup::registered_routes;
+
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn openapi_spec_is_served_as_json() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/api/openapi.json", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/json"
+    );
+    let spec: serde_json::Value = response.json().await.unwrap();
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+}
+
+#[tokio::test]
+async fn every_registered_route_is_documented() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let spec = app.get_openapi_spec().await;
+
+    // Assert
+    let missing: Vec<_> = registered_routes()
+        .into_iter()
+        .filter(|(method, path)| spec["paths"][path][method.as_str().to_lowercase()].is_null())
+        .map(|(method, path)| format!("{} {}", method, path))
+        .collect();
+    assert!(missing.is_empty(), "Undocumented routes: {:?}", missing);
+}
+
+#[tokio::test]
+async fn the_spec_does_not_document_unregistered_routes() {
+    // Arrange
+    let app = spawn_app().await;
+    let registered: HashSet<_> = registered_routes()
+        .into_iter()
+        .map(|(method, path)| (method.as_str().to_lowercase(), path))
+        .collect();
+
+    // Act
+    let spec = app.get_openapi_spec().await;
+
+    // Assert
+    let mut unknown = Vec::new();
+    for (path, operations) in spec["paths"].as_object().unwrap() {
+        for method in operations.as_object().unwrap().keys() {
+            if !registered.contains(&(method.clone(), path.clone())) {
+                unknown.push(format!("{} {}", method, path));
+            }
+        }
+    }
+    assert!(unknown.is_empty(), "Unregistered routes: {:?}", unknown);
+}
+
+#[tokio::test]
+async fn request_and_error_types_are_described() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let spec = app.get_openapi_spec().await;
+
+    // Assert
+    let subscribe = &spec["paths"]["/subscriptions"]["post"];
+    let form = &subscribe["requestBody"]["content"]["application/x-www-form-urlencoded"]["schema"];
+    let form = match form["$ref"].as_str() {
+        Some(reference) => {
+            let name = reference.rsplit('/').next().unwrap();
+            &spec["components"]["schemas"][name]
+        }
+        None => form,
+    };
+    assert!(!form["properties"]["email"].is_null());
+    assert!(!form["properties"]["name"].is_null());
+    assert!(!subscribe["responses"]["400"].is_null());
+    assert!(!subscribe["responses"]["500"].is_null());
+
+    let confirm = &spec["paths"]["/subscriptions/confirm"]["get"];
+    assert!(!confirm["responses"]["401"].is_null());
+
+    let schemas = spec["components"]["schemas"].as_object().unwrap();
+    for schema in ["ProblemDetails", "Subscriber", "IssueStatus"] {
+        assert!(schemas.contains_key(schema), "Missing schema {}", schema);
+    }
+}
@@ -0,0 +1,86 @@
+use reqwest::header::{
+    CONTENT_SECURITY_POLICY, REFERRER_POLICY, SET_COOKIE, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn responses_carry_the_security_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    for path in ["/login", "/health_check", "/does-not-exist"] {
+        let response = app
+            .api_client
+            .get(format!("{}{}", app.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request");
+
+        // Assert
+        let headers = response.headers();
+        assert!(headers[CONTENT_SECURITY_POLICY]
+            .to_str()
+            .unwrap()
+            .contains("frame-ancestors 'none'"));
+        assert_eq!(headers[X_FRAME_OPTIONS], "DENY", "{}", path);
+        assert_eq!(headers[REFERRER_POLICY], "same-origin", "{}", path);
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff", "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn security_headers_can_be_turned_off() {
+    // Arrange
+    let app = spawn_app_with(|c| c.security_headers.frame_options = String::new()).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/login", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert!(response.headers().get(X_FRAME_OPTIONS).is_none());
+    assert!(response.headers().get(REFERRER_POLICY).is_some());
+}
+
+// The login form stores its CSRF token in the session, which sets the cookie
+async fn session_cookie(secure: bool) -> String {
+    let app = spawn_app_with(|c| c.sessions.cookie_secure = secure).await;
+    let response = app
+        .api_client
+        .get(format!("{}/login", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap().to_string())
+        .find(|cookie| cookie.starts_with("id="))
+        .expect("No session cookie")
+}
+
+#[tokio::test]
+async fn the_session_cookie_is_hardened() {
+    let cookie = session_cookie(true).await;
+
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("SameSite=Strict"));
+    assert!(cookie.contains("Secure"));
+    // It lasts as long as the browser session
+    assert!(!cookie.contains("Max-Age"));
+}
+
+#[tokio::test]
+async fn the_session_cookie_can_be_sent_over_plain_http() {
+    let cookie = session_cookie(false).await;
+
+    assert!(cookie.contains("HttpOnly"));
+    assert!(!cookie.contains("Secure"));
+}
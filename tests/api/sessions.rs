@@ -0,0 +1,173 @@
+use crate::helpers::{extract_csrf_token, spawn_app, spawn_app_with, TestApp};
+use crate::utils::assert_redirect_is_to;
+
+// Log the test user in from another device, with its own cookie jar
+async fn login_from_another_device(app: &TestApp) -> reqwest::Client {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .user_agent("Other device")
+        .build()
+        .unwrap();
+    let login_page = client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let response = client
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "csrf_token": extract_csrf_token(&login_page),
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_redirect_is_to(&response, "/admin/dashboard");
+    client
+}
+
+async fn get_dashboard(client: &reqwest::Client, app: &TestApp) -> reqwest::Response {
+    client
+        .get(format!("{}/admin/dashboard", &app.address))
+        .send()
+        .await
+        .unwrap()
+}
+
+fn revocable_session_ids(html_page: &str) -> Vec<String> {
+    html_page
+        .split(r#"name="session_id" value=""#)
+        .skip(1)
+        .map(|s| s.split('"').next().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    let response = app.get_sessions().await;
+
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn sessions_page_lists_every_device() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    login_from_another_device(&app).await;
+
+    let html_page = app.get_sessions_html().await;
+
+    assert!(html_page.contains("This session"));
+    assert!(html_page.contains("Other device"));
+    assert!(html_page.contains("127.0.0.1"));
+    assert_eq!(revocable_session_ids(&html_page).len(), 1);
+}
+
+#[tokio::test]
+async fn a_revoked_session_is_logged_out() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = login_from_another_device(&app).await;
+    let session_id = revocable_session_ids(&app.get_sessions_html().await).remove(0);
+
+    let response = app
+        .post_revoke_session(&serde_json::json!({ "session_id": session_id }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/sessions");
+
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("<p><i>The session has been revoked</i></p>"));
+    assert!(!html_page.contains("Other device"));
+    let response = get_dashboard(&other_device, &app).await;
+    assert_redirect_is_to(&response, "/login");
+    // Our own session is unaffected
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_revoke_session(&serde_json::json!({ "session_id": uuid::Uuid::new_v4() }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/sessions");
+
+    let html_page = app.get_sessions_html().await;
+    assert!(html_page.contains("The session does not exist or has already ended"));
+}
+
+#[tokio::test]
+async fn changing_password_revokes_all_other_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let other_device = login_from_another_device(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "old_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_confirm": &new_password,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/password");
+
+    let response = get_dashboard(&other_device, &app).await;
+    assert_redirect_is_to(&response, "/login");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn logging_out_removes_the_session() {
+    let app = spawn_app().await;
+    let other_device = login_from_another_device(&app).await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+
+    let sessions_page = other_device
+        .get(format!("{}/admin/sessions", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(sessions_page.contains("This session"));
+    assert!(revocable_session_ids(&sessions_page).is_empty());
+}
+
+#[tokio::test]
+async fn a_session_expires_after_its_ttl_however_active() {
+    let app = spawn_app_with(|c| c.sessions.ttl_seconds = 3600).await;
+    app.test_user.login(&app).await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clock.advance(chrono::Duration::hours(1));
+    let response = app.get_admin_dashboard().await;
+
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn an_idle_session_expires() {
+    let app = spawn_app_with(|c| c.sessions.idle_timeout_seconds = 1).await;
+    app.test_user.login(&app).await;
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    let response = app.get_admin_dashboard().await;
+
+    assert_redirect_is_to(&response, "/login");
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::{queue_stats, worker_loop};
+
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletters::create_confirmed_subscriber;
+use crate::utils::assert_redirect_is_to;
+
+// Wait until the email server got `n` requests, i.e. until they are in flight
+async fn wait_for_email_requests(app: &TestApp, n: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while app.email_server.received_requests().await.unwrap().len() < n {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The email was never sent");
+}
+
+#[tokio::test]
+async fn in_flight_requests_complete_when_the_server_shuts_down() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let in_flight = {
+        let client = app.api_client.clone();
+        let url = format!("{}/subscriptions", app.address);
+        tokio::spawn(async move {
+            client
+                .post(url)
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+                .send()
+                .await
+        })
+    };
+    wait_for_email_requests(&app, 1).await;
+
+    // Act
+    app.shutdown.cancel();
+
+    // Assert
+    let response = in_flight.await.unwrap().unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let new_request = reqwest::Client::new()
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await;
+    assert!(new_request.is_err());
+}
+
+#[tokio::test]
+async fn the_worker_finishes_its_delivery_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "text_content": "Newsletter body as plain text",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/newsletters");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(worker_loop(
+        app.db_pool.clone(),
+        app.email_client.clone(),
+        shutdown.clone(),
+    ));
+    // The confirmation email was the first request
+    wait_for_email_requests(&app, 2).await;
+
+    // Act
+    shutdown.cancel();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The worker did not stop")
+        .unwrap()
+        .unwrap();
+    assert!(queue_stats(&app.db_pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn an_idle_worker_stops_right_away() {
+    // Arrange
+    let app = spawn_app().await;
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(worker_loop(
+        app.db_pool.clone(),
+        app.email_client.clone(),
+        shutdown.clone(),
+    ));
+    // Let the worker find the queue empty and go to sleep
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act
+    shutdown.cancel();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(1), worker)
+        .await
+        .expect("The worker did not stop")
+        .unwrap()
+        .unwrap();
+}
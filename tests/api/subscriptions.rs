@@ -1,8 +1,11 @@
+use reqwest::header::RETRY_AFTER;
 use reqwest::StatusCode;
-use wiremock::matchers::{method, path};
-use wiremock::{Mock, ResponseTemplate};
+use secrecy::Secret;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::config::ChallengeSettings;
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn when_subscribe_with_valid_form_data_return_200() {
@@ -143,3 +146,224 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscriptions_from_the_same_ip_are_throttled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscription_protection.max_per_ip = 2).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    for domain in ["gmail.com", "yahoo.com"] {
+        let body = format!("name=bryan&email=bryan%40{}", domain);
+        assert_eq!(app.post_subscriptions(body).await.status(), StatusCode::OK);
+    }
+
+    // Act
+    let response = app
+        .post_subscriptions("name=bryan&email=bryan%40proton.me".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().get(RETRY_AFTER).is_some());
+}
+
+#[tokio::test]
+async fn subscriptions_to_the_same_email_domain_are_throttled() {
+    // Arrange
+    let app = spawn_app_with(|c| c.subscription_protection.max_per_email_domain = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=bryan&email=bryan%40example.com".into())
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=ursula&email=ursula%40EXAMPLE.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn subscriptions_with_a_filled_in_honeypot_are_ignored() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=bryan&email=bryan%40gmail.com&website=spam.biz".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::OK);
+    let subscribers: i64 = sqlx::query_scalar("SELECT count(*) FROM subscription")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscribers, 0);
+}
+
+#[tokio::test]
+async fn subscriptions_must_solve_the_challenge_when_one_is_configured() {
+    // Arrange
+    let challenge_server = MockServer::start().await;
+    let verify_url = format!("{}/siteverify", challenge_server.uri());
+    let app = spawn_app_with(|c| {
+        c.subscription_protection.challenge = Some(ChallengeSettings {
+            verify_url,
+            secret: Secret::new("challenge-secret".into()),
+            timeout_milliseconds: 2000,
+        })
+    })
+    .await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=solved"))
+        .and(body_string_contains("secret=challenge-secret"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": true})),
+        )
+        .mount(&challenge_server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=forged"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({"success": false})),
+        )
+        .mount(&challenge_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let body = "name=bryan&email=bryan%40gmail.com";
+
+    // Act
+    let missing = app.post_subscriptions(body.into()).await;
+    let forged = app
+        .post_subscriptions(format!("{}&challenge_response=forged", body))
+        .await;
+    let solved = app
+        .post_subscriptions(format!("{}&challenge_response=solved", body))
+        .await;
+
+    // Assert
+    assert_eq!(missing.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(forged.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(solved.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn disposable_and_role_addresses_are_refused_when_the_policy_says_so() {
+    // Arrange
+    let app = spawn_app_with(|c| {
+        c.subscriber_email.reject_disposable = true;
+        c.subscriber_email.reject_role_accounts = true;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for (body, description) in [
+        ("name=bryan&email=bryan%40mailinator.com", "disposable"),
+        ("name=bryan&email=noreply%40gmail.com", "role account"),
+    ] {
+        // Act
+        let response = app.post_subscriptions(body.into()).await;
+
+        // Assert
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "The API accepted a {} address",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn emails_differing_only_by_case_are_the_same_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first_response = app
+        .post_subscriptions("name=alice&email=Alice%40Example.com".into())
+        .await;
+    let second_response = app
+        .post_subscriptions("name=alice&email=alice%40example.COM".into())
+        .await;
+
+    // Assert - The pending subscriber got a second confirmation email
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let emails: Vec<String> = sqlx::query_scalar("SELECT email FROM subscription")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(emails, vec!["alice@example.com".to_string()]);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_ne!(
+        app.get_confirmation_links(&email_requests[0]).html,
+        app.get_confirmation_links(&email_requests[1]).html
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_subscribing_again_are_not_emailed() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app.post_subscriptions(body.into()).await;
+
+    // Assert - The mock checks that no other email was sent
+    assert_eq!(response.status().as_u16(), 200);
+    let status: String = sqlx::query_scalar("SELECT status FROM subscription")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "confirmed");
+}
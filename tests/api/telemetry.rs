@@ -0,0 +1,125 @@
+use actix_web::{test, web, App};
+use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+use opentelemetry_sdk::trace::TracerProvider;
+use secrecy::Secret;
+use tracing::Instrument;
+use tracing_actix_web::TracingLogger;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::email_client::EmailClient;
+use zero2prod::telemetry::{get_subscriber, init_trace_context_propagation, otlp_tracer};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+fn current_trace_id() -> String {
+    tracing::Span::current()
+        .context()
+        .span()
+        .span_context()
+        .trace_id()
+        .to_string()
+}
+
+// Spans are recorded but not exported anywhere
+fn local_tracer_provider() -> TracerProvider {
+    TracerProvider::builder().build()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_are_exported_to_the_otlp_collector() {
+    // Arrange
+    let collector = MockServer::start().await;
+    Mock::given(path("/v1/traces"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1..)
+        .mount(&collector)
+        .await;
+    let tracer = otlp_tracer("zero2prod-test", &collector.uri()).unwrap();
+    let (subscriber, _) = get_subscriber("test", "info", std::io::sink, Some(tracer.clone()));
+
+    // Act
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::info_span!("exported_span").in_scope(|| {});
+    });
+    tracer.provider().unwrap().force_flush();
+
+    // Assert
+    let requests = collector.received_requests().await.unwrap();
+    assert!(requests
+        .iter()
+        .any(|r| String::from_utf8_lossy(&r.body).contains("exported_span")));
+}
+
+#[actix_web::test]
+async fn incoming_requests_continue_the_trace_of_their_traceparent_header() {
+    // Arrange
+    let provider = local_tracer_provider();
+    let (subscriber, _) =
+        get_subscriber("test", "info", std::io::sink, Some(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+    init_trace_context_propagation();
+    let app = test::init_service(
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/", web::get().to(|| async { current_trace_id() })),
+    )
+    .await;
+
+    // Act
+    let request = test::TestRequest::get()
+        .uri("/")
+        .insert_header((
+            "traceparent",
+            format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+        ))
+        .to_request();
+    let body = test::call_and_read_body(&app, request).await;
+
+    // Assert
+    assert_eq!(body, TRACE_ID);
+}
+
+#[tokio::test]
+async fn outgoing_emails_carry_the_current_trace_context() {
+    // Arrange
+    let provider = local_tracer_provider();
+    let (subscriber, _) =
+        get_subscriber("test", "info", std::io::sink, Some(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+    init_trace_context_propagation();
+    let email_server = MockServer::start().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&email_server)
+        .await;
+    let email_client = EmailClient::new(
+        email_server.uri(),
+        SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+        Secret::new("token".into()),
+        std::time::Duration::from_secs(1),
+    );
+    let recipient = SubscriberEmail::parse("recipient@example.com".into()).unwrap();
+
+    // Act
+    let span = tracing::info_span!("sending_email");
+    let trace_id = span.in_scope(current_trace_id);
+    email_client
+        .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+        .instrument(span)
+        .await
+        .unwrap();
+
+    // Assert
+    let requests = email_server.received_requests().await.unwrap();
+    let traceparent = requests[0]
+        .headers
+        .get("traceparent")
+        .expect("No traceparent header")
+        .to_str()
+        .unwrap();
+    assert!(traceparent.contains(&trace_id));
+}
@@ -0,0 +1,103 @@
+use reqwest::header::{LOCATION, STRICT_TRANSPORT_SECURITY};
+use reqwest::{Certificate, StatusCode, Version};
+use uuid::Uuid;
+use zero2prod::config::TlsSettings;
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+// A self-signed certificate for 127.0.0.1, along with a client trusting it
+fn self_signed_certificate() -> (TlsSettings, reqwest::Client) {
+    let rcgen::CertifiedKey { cert, key_pair } =
+        rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+    std::fs::create_dir(&dir).unwrap();
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    std::fs::write(&cert_path, cert.pem()).unwrap();
+    std::fs::write(&key_path, key_pair.serialize_pem()).unwrap();
+
+    let settings = TlsSettings {
+        cert_path: cert_path.to_str().unwrap().to_string(),
+        key_path: key_path.to_str().unwrap().to_string(),
+        redirect_port: None,
+        hsts_max_age_seconds: 3600,
+    };
+    let client = reqwest::Client::builder()
+        .add_root_certificate(Certificate::from_pem(cert.pem().as_bytes()).unwrap())
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    (settings, client)
+}
+
+#[tokio::test]
+async fn requests_are_served_over_tls_with_http2() {
+    // Arrange
+    let (tls, client) = self_signed_certificate();
+    let app = spawn_app_with(|c| c.application.tls = Some(tls)).await;
+
+    // Act
+    let response = client
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert!(app.address.starts_with("https://"));
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.version(), Version::HTTP_2);
+    assert_eq!(
+        response.headers()[STRICT_TRANSPORT_SECURITY],
+        "max-age=3600"
+    );
+}
+
+#[tokio::test]
+async fn plain_http_is_redirected_to_the_https_base_url() {
+    // Arrange
+    let (mut tls, client) = self_signed_certificate();
+    tls.redirect_port = Some(0);
+    let app = spawn_app_with(|c| {
+        c.application.tls = Some(tls);
+        c.application.base_url = "https://zero2prod.test".into();
+    })
+    .await;
+    let redirect_port = app.https_redirect_port.unwrap();
+
+    // Act
+    let response = client
+        .post(format!(
+            "http://127.0.0.1:{}/subscriptions?source=footer",
+            redirect_port
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://zero2prod.test/subscriptions?source=footer"
+    );
+    assert!(response.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+}
+
+#[tokio::test]
+async fn hsts_is_not_sent_without_tls() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/health_check", app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert!(app.https_redirect_port.is_none());
+    assert!(response.headers().get(STRICT_TRANSPORT_SECURITY).is_none());
+}
@@ -0,0 +1,217 @@
+use chrono::Duration;
+use reqwest::StatusCode;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use crate::utils::assert_redirect_is_to;
+
+fn totp_code(secret: &str, app: &TestApp) -> String {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 0, 30, secret, None, "".to_string())
+        .unwrap()
+        .generate(app.clock.now().timestamp() as u64)
+}
+
+fn extract_between<'a>(html: &'a str, start: &str, end: &str) -> &'a str {
+    let from = html.find(start).expect("Could not find start marker") + start.len();
+    let to = from + html[from..].find(end).expect("Could not find end marker");
+    &html[from..to]
+}
+
+// Log in and go through the enrollment flow, returning the TOTP secret and the recovery codes
+async fn enable_totp(app: &TestApp) -> (String, Vec<String>) {
+    app.test_user.login(app).await;
+    let response = app.post_start_totp_enrollment().await;
+    assert_redirect_is_to(&response, "/admin/security");
+
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("otpauth://totp/"));
+    let secret = extract_between(&html_page, "with the key <code>", "</code>").to_string();
+
+    let response = app
+        .post_confirm_totp_enrollment(&serde_json::json!({
+            "code": totp_code(&secret, app)
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let recovery_codes = html_page
+        .split("<li><code>")
+        .skip(1)
+        .map(|s| s.split("</code>").next().unwrap().to_string())
+        .collect();
+
+    app.post_logout().await;
+    // Codes are single-use, move on to the next time step
+    app.clock.advance(Duration::seconds(30));
+    (secret, recovery_codes)
+}
+
+#[tokio::test]
+async fn enrollment_requires_a_valid_code() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_start_totp_enrollment().await;
+
+    let response = app
+        .post_confirm_totp_enrollment(&serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/security");
+
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>The authentication code is incorrect</i></p>"));
+    assert!(!html_page.contains("Two-factor authentication is <b>enabled</b>"));
+}
+
+#[tokio::test]
+async fn login_requires_a_second_factor_once_totp_is_enabled() {
+    let app = spawn_app().await;
+    let (secret, _) = enable_totp(&app).await;
+
+    // Act - Part 1 - Password only gets us half way
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_redirect_is_to(&response, "/login");
+
+    // Act - Part 2 - Provide the code
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": totp_code(&secret, &app) }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+    let html_page = app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn an_invalid_second_factor_is_rejected() {
+    let app = spawn_app().await;
+    enable_totp(&app).await;
+    app.test_user.login(&app).await;
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_redirect_is_to(&response, "/login/two-factor");
+
+    let html_page = app.get_login_two_factor_html().await;
+    assert!(html_page.contains("Authentication failed"));
+    let response = app.get_admin_dashboard().await;
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn resubmitting_the_password_does_not_reset_second_factor_failures() {
+    // Arrange
+    let app = spawn_app_with(|c| c.login_throttling.max_failures_per_username = 3).await;
+    enable_totp(&app).await;
+
+    // Act - The password is correct, the codes are guesses
+    for _ in 0..3 {
+        let response = app
+            .post_login(&serde_json::json!({
+                "username": app.test_user.username,
+                "password": app.test_user.password,
+            }))
+            .await;
+        assert_redirect_is_to(&response, "/login/two-factor");
+        let response = app
+            .post_login_two_factor(&serde_json::json!({ "code": "123456" }))
+            .await;
+        assert_redirect_is_to(&response, "/login/two-factor");
+    }
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn a_totp_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    let (secret, _) = enable_totp(&app).await;
+    let code = totp_code(&secret, &app);
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &code }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &code }))
+        .await;
+    assert_redirect_is_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn a_recovery_code_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enable_totp(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &recovery_codes[0] }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": &recovery_codes[0] }))
+        .await;
+    assert_redirect_is_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_factor_page_requires_a_verified_password() {
+    let app = spawn_app().await;
+
+    let response = app.get_login_two_factor().await;
+    assert_redirect_is_to(&response, "/login");
+
+    let response = app
+        .post_login_two_factor(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_redirect_is_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn totp_can_be_disabled_with_a_valid_code() {
+    let app = spawn_app().await;
+    let (secret, _) = enable_totp(&app).await;
+    app.test_user.login(&app).await;
+    app.post_login_two_factor(&serde_json::json!({ "code": totp_code(&secret, &app) }))
+        .await;
+    app.clock.advance(Duration::seconds(30));
+
+    let response = app
+        .post_disable_totp(&serde_json::json!({ "code": totp_code(&secret, &app) }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/security");
+    let html_page = app.get_security_html().await;
+    assert!(html_page.contains("<p><i>Two-factor authentication has been disabled</i></p>"));
+
+    // Logging in only takes a password again
+    app.post_logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await;
+    assert_redirect_is_to(&response, "/admin/dashboard");
+}